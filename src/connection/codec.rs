@@ -0,0 +1,215 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+// how encrypted payloads are serialized; JSON until the handshake agrees on something else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "msgpack" => Some(Self::MessagePack),
+            _ => None,
+        }
+    }
+
+    // in order of preference; binary encodings need a transport that carries binary frames
+    pub fn offered(binary: bool) -> Vec<&'static str> {
+        if binary {
+            vec![Self::MessagePack.name(), Self::Json.name()]
+        } else {
+            vec![Self::Json.name()]
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        *self != Self::Json
+    }
+
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, AppError> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(AppError::SerializationError),
+            // named fields keep internally tagged enums readable on the other side
+            Self::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| AppError::EncodingError(e.to_string()))
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, AppError> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(AppError::SerializationError),
+            Self::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|e| AppError::EncodingError(e.to_string()))
+            }
+        }
+    }
+}
+
+// applied to the serialized payload before encryption, where transport compression can't reach.
+// the data is raw DEFLATE (RFC 1951) with no zlib or gzip header, hence the name on the wire.
+// compressing secrets together with attacker-influenced input leaks content through the
+// ciphertext length (CRIME/BREACH), so messages carrying user input are never compressed
+// here, and servers should likewise skip pages that reflect input next to tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    // below this, the deflate header and the flag cost more than they save
+    pub const THRESHOLD_BYTES: usize = 1024;
+    const LEVEL: u8 = 6;
+    const MAX_INFLATED_BYTES: usize = 64 * 1024 * 1024;
+    const FLAG_RAW: u8 = 0;
+    const FLAG_DEFLATE: u8 = 1;
+    // form data and push payloads are user input
+    const USER_INPUT_ACTIONS: [&'static str; 2] = ["event_callback", "push"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Deflate => "deflate-raw",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "deflate-raw" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    pub fn offered() -> Vec<&'static str> {
+        vec![Self::Deflate.name()]
+    }
+
+    pub fn compressible(action: &str) -> bool {
+        !Self::USER_INPUT_ACTIONS.contains(&action)
+    }
+
+    // None when the payload goes out as is
+    pub fn compress(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => None,
+            Self::Deflate if bytes.len() < Self::THRESHOLD_BYTES => None,
+            Self::Deflate => Some(miniz_oxide::deflate::compress_to_vec(bytes, Self::LEVEL)),
+        }
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, AppError> {
+        match self {
+            Self::None => Ok(bytes.to_vec()),
+            Self::Deflate => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, Self::MAX_INFLATED_BYTES)
+                    .map_err(|e| AppError::EncodingError(format!("inflate failed: {:?}", e)))
+            }
+        }
+    }
+
+    // binary frames have no envelope, so once negotiated every payload starts with a flag byte
+    pub fn pack(&self, bytes: Vec<u8>, compressible: bool) -> Vec<u8> {
+        if *self == Self::None {
+            return bytes;
+        }
+        match self.compress(&bytes).filter(|_| compressible) {
+            Some(compressed) => [&[Self::FLAG_DEFLATE][..], &compressed].concat(),
+            None => [&[Self::FLAG_RAW][..], &bytes].concat(),
+        }
+    }
+
+    pub fn unpack(&self, bytes: Vec<u8>) -> Result<Vec<u8>, AppError> {
+        if *self == Self::None {
+            return Ok(bytes);
+        }
+        match bytes.split_first() {
+            Some((&Self::FLAG_RAW, rest)) => Ok(rest.to_vec()),
+            Some((&Self::FLAG_DEFLATE, rest)) => self.decompress(rest),
+            _ => Err(AppError::EncodingError(
+                "unknown compression flag".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repetitive(len: usize) -> Vec<u8> {
+        b"<div class=\"row\">"
+            .iter()
+            .copied()
+            .cycle()
+            .take(len)
+            .collect()
+    }
+
+    #[test]
+    fn small_payloads_are_not_compressed() {
+        let bytes = repetitive(Compression::THRESHOLD_BYTES - 1);
+        assert_eq!(Compression::Deflate.compress(&bytes), None);
+        let packed = Compression::Deflate.pack(bytes.clone(), true);
+        assert_eq!(packed[0], 0);
+        assert_eq!(Compression::Deflate.unpack(packed).unwrap(), bytes);
+    }
+
+    #[test]
+    fn large_payloads_round_trip() {
+        let bytes = repetitive(64 * 1024);
+        let packed = Compression::Deflate.pack(bytes.clone(), true);
+        assert_eq!(packed[0], 1);
+        assert!(packed.len() < bytes.len() / 10);
+        assert_eq!(Compression::Deflate.unpack(packed).unwrap(), bytes);
+    }
+
+    #[test]
+    fn user_input_is_sent_raw() {
+        assert!(!Compression::compressible("event_callback"));
+        assert!(Compression::compressible("navigate"));
+        let bytes = repetitive(64 * 1024);
+        let packed = Compression::Deflate.pack(bytes.clone(), false);
+        assert_eq!(packed[0], 0);
+        assert_eq!(&packed[1..], bytes.as_slice());
+    }
+
+    #[test]
+    fn no_compression_adds_no_flag() {
+        let bytes = repetitive(4096);
+        assert_eq!(Compression::None.pack(bytes.clone(), true), bytes);
+        assert_eq!(Compression::None.unpack(bytes.clone()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert!(Compression::Deflate.unpack(vec![7, 1, 2]).is_err());
+        assert!(Compression::Deflate.unpack(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn negotiates_by_wire_name() {
+        assert_eq!(
+            Compression::from_name("deflate-raw"),
+            Some(Compression::Deflate)
+        );
+        assert_eq!(Compression::from_name("deflate"), None);
+        assert_eq!(Compression::offered(), vec!["deflate-raw"]);
+    }
+}
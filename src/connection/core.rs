@@ -1,33 +1,63 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use web_sys::{WebSocket, Window};
+use web_sys::Window;
 
+use crate::connection::ConnectionStatus;
 use crate::connection::Crypto;
 use crate::connection::EventHandler;
+use crate::connection::Heartbeat;
+use crate::connection::MessageHandler;
+use crate::connection::Reconnector;
+use crate::connection::Session;
+use crate::connection::Socket;
+use crate::connection::Subscriptions;
+use crate::connection::TransportSelector;
+use crate::connection::Uploads;
+use crate::connection::{ActionHandler, Dispatcher};
 use crate::error::AppError;
+use crate::utils::MetaConfig;
 use crate::vdom::VirtualDom;
 
 #[derive(Clone)]
 pub struct ClientConnection {
-    pub ws: WebSocket,
+    pub ws: Socket,
     pub window: Window,
     pub vdom: Rc<RefCell<Option<VirtualDom>>>,
     pub crypto: Rc<RefCell<Crypto>>,
-    pub ws_gateway: String,
+    pub subscriptions: Rc<RefCell<Subscriptions>>,
+    pub uploads: Rc<RefCell<Uploads>>,
+    pub reconnector: Rc<Reconnector>,
+    pub status: Rc<ConnectionStatus>,
+    pub session: Rc<Session>,
+    pub heartbeat: Rc<Heartbeat>,
+    pub transports: Rc<TransportSelector>,
+    pub dispatcher: Rc<RefCell<Dispatcher>>,
 }
 
 impl ClientConnection {
-    pub fn new(server_url: &str) -> Result<Self, AppError> {
+    pub fn new(config: &MetaConfig) -> Result<Self, AppError> {
         let window = web_sys::window().ok_or(AppError::WindowNotFound)?;
-        let ws = WebSocket::new(server_url)
-            .map_err(|e| AppError::WebSocketError(e.as_string().unwrap_or_default()))?;
+        let transports = Rc::new(TransportSelector::new(config));
+        let ws = Socket::new(transports.connect()?);
+
+        let status = Rc::new(ConnectionStatus::new(&window));
+        let session = Rc::new(Session::new(&window));
+        let mut dispatcher = Dispatcher::new();
+        MessageHandler::register_defaults(&mut dispatcher);
 
         Ok(Self {
             ws,
             window,
             vdom: Rc::new(RefCell::new(None)),
             crypto: Rc::new(RefCell::new(Crypto::new())),
-            ws_gateway: server_url.to_string(),
+            subscriptions: Rc::new(RefCell::new(Subscriptions::new())),
+            uploads: Rc::new(RefCell::new(Uploads::new())),
+            reconnector: Rc::new(Reconnector::new(config)),
+            status,
+            session,
+            heartbeat: Rc::new(Heartbeat::new(config)),
+            transports,
+            dispatcher: Rc::new(RefCell::new(dispatcher)),
         })
     }
 
@@ -37,7 +67,16 @@ impl ClientConnection {
         Ok(())
     }
 
+    // runs after any handler already registered for the action
+    pub fn register(&self, action: &'static str, handler: impl ActionHandler + 'static) {
+        self.dispatcher.borrow_mut().register(action, handler);
+    }
+
     pub fn get_crypto_ref(&self) -> Rc<RefCell<Crypto>> {
         self.crypto.clone()
     }
+
+    pub fn get_uploads_ref(&self) -> Rc<RefCell<Uploads>> {
+        self.uploads.clone()
+    }
 }
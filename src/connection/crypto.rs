@@ -1,8 +1,9 @@
 use crate::utils::log;
 use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
 use rand::RngCore;
 use rand::rngs::OsRng;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -22,9 +23,11 @@ pub struct Crypto {
 }
 
 impl Crypto {
+    const NONCE_LEN: usize = 12;
+
     pub fn new() -> Self {
-        let mut rng = OsRng;
-        let x25519_secret = StaticSecret::random_from_rng(&mut rng);
+        let rng = OsRng;
+        let x25519_secret = StaticSecret::random_from_rng(rng);
         let x25519_public = PublicKey::from(&x25519_secret);
 
         Self {
@@ -39,40 +42,63 @@ impl Crypto {
         general_purpose::STANDARD.encode(public_key_bytes)
     }
 
+    // drops the session key so nothing is encrypted for a server session that is gone
+    pub fn clear_session(&self) {
+        *self.aes_cipher.borrow_mut() = None;
+    }
+
     pub fn derive_shared_secret(&self, server_public_key_b64: &str) -> Result<(), AppError> {
-        match general_purpose::STANDARD.decode(server_public_key_b64) {
-            Ok(server_public_key_bytes) => {
-                if server_public_key_bytes.len() == 32 {
-                    let mut server_public_key_array = [0u8; 32];
-                    server_public_key_array.copy_from_slice(&server_public_key_bytes);
-
-                    let server_public = PublicKey::from(server_public_key_array);
-                    let shared_secret = self.x25519_secret.diffie_hellman(&server_public);
-                    let hk = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
-                    let mut aes_key = [0u8; 32];
-                    hk.expand(b"quillion-aes-key", &mut aes_key)
-                        .expect("hkdf expansion - fail");
-
-                    let cipher =
-                        Aes256Gcm::new_from_slice(&aes_key).expect("aes key derivation - failed");
-
-                    *self.aes_cipher.borrow_mut() = Some(cipher);
-                    Ok(())
-                } else {
-                    Err(AppError::CryptoError(format!(
-                        "Invalid public key, length: {}",
-                        server_public_key_bytes.len()
-                    )))
-                }
-            }
-            Err(e) => Err(AppError::CryptoError(format!(
-                "Base64 decode error: {:?}",
-                e
-            ))),
-        }
+        let server_public = Self::decode_public_key(server_public_key_b64)?;
+        let shared_secret = self.x25519_secret.diffie_hellman(&server_public);
+        let hk = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
+        let mut aes_key = [0u8; 32];
+        hk.expand(b"quillion-aes-key", &mut aes_key)
+            .expect("hkdf expansion - fail");
+
+        let cipher = Aes256Gcm::new_from_slice(&aes_key).expect("aes key derivation - failed");
+
+        *self.aes_cipher.borrow_mut() = Some(cipher);
+        Ok(())
+    }
+
+    // names the session without revealing the token, so the server can find it to check a proof
+    pub fn session_id(token: &str) -> String {
+        general_purpose::STANDARD.encode(Sha256::digest(token.as_bytes()))
+    }
+
+    // HMAC-SHA256 keyed by the session token over both public keys of this handshake. only a
+    // holder of the token can produce it, and it is worthless in any other key exchange
+    pub fn resume_proof(
+        &self,
+        token: &str,
+        server_public_key_b64: &str,
+    ) -> Result<String, AppError> {
+        let server_public = Self::decode_public_key(server_public_key_b64)?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(token.as_bytes())
+            .map_err(|e| AppError::CryptoError(e.to_string()))?;
+        mac.update(self.x25519_public.as_bytes());
+        mac.update(server_public.as_bytes());
+        Ok(general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+    }
+
+    fn decode_public_key(public_key_b64: &str) -> Result<PublicKey, AppError> {
+        let public_key_bytes = general_purpose::STANDARD
+            .decode(public_key_b64)
+            .map_err(|e| AppError::CryptoError(format!("Base64 decode error: {:?}", e)))?;
+        let public_key_array: [u8; 32] = public_key_bytes.as_slice().try_into().map_err(|_| {
+            AppError::CryptoError(format!(
+                "Invalid public key, length: {}",
+                public_key_bytes.len()
+            ))
+        })?;
+        Ok(PublicKey::from(public_key_array))
     }
 
     pub fn encrypt(&self, message: &str) -> Option<(String, String)> {
+        self.encrypt_bytes(message.as_bytes())
+    }
+
+    pub fn encrypt_bytes(&self, message: &[u8]) -> Option<(String, String)> {
         if let Some(cipher) = self.aes_cipher.borrow().as_ref() {
             let mut rng = OsRng;
             let mut nonce_bytes = [0u8; 12];
@@ -80,14 +106,14 @@ impl Crypto {
             let nonce = Nonce::from_slice(&nonce_bytes);
 
             let payload = Payload {
-                msg: message.as_bytes(),
+                msg: message,
                 aad: &[],
             };
 
             match cipher.encrypt(nonce, payload) {
                 Ok(ciphertext) => Some((
                     general_purpose::STANDARD.encode(&ciphertext),
-                    general_purpose::STANDARD.encode(&nonce_bytes),
+                    general_purpose::STANDARD.encode(nonce_bytes),
                 )),
                 Err(e) => {
                     log(&format!("Encryption error: {:?}", e));
@@ -100,6 +126,11 @@ impl Crypto {
     }
 
     pub fn decrypt(&self, encrypted_payload_b64: &str, nonce_b64: &str) -> Option<String> {
+        self.decrypt_bytes(encrypted_payload_b64, nonce_b64)
+            .and_then(|decrypted_bytes| String::from_utf8(decrypted_bytes).ok())
+    }
+
+    pub fn decrypt_bytes(&self, encrypted_payload_b64: &str, nonce_b64: &str) -> Option<Vec<u8>> {
         if let Some(cipher) = self.aes_cipher.borrow().as_ref() {
             match (
                 general_purpose::STANDARD.decode(encrypted_payload_b64),
@@ -114,7 +145,7 @@ impl Crypto {
                         };
 
                         match cipher.decrypt(nonce, payload) {
-                            Ok(decrypted_bytes) => String::from_utf8(decrypted_bytes).ok(),
+                            Ok(decrypted_bytes) => Some(decrypted_bytes),
                             Err(e) => {
                                 log(&format!("Decryption failed: {:?}", e));
                                 None
@@ -138,8 +169,92 @@ impl Crypto {
                 }
             }
         } else {
-            log(&"AES cipher not initialized");
+            log("AES cipher not initialized");
             None
         }
     }
+
+    // binary frames carry the nonce followed by the ciphertext, without any base64
+    pub fn encrypt_frame(&self, message: &[u8]) -> Option<Vec<u8>> {
+        let cipher = self.aes_cipher.borrow();
+        let cipher = cipher.as_ref()?;
+
+        let mut nonce_bytes = [0u8; Self::NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce_bytes);
+        let payload = Payload {
+            msg: message,
+            aad: &[],
+        };
+
+        match cipher.encrypt(Nonce::from_slice(&nonce_bytes), payload) {
+            Ok(ciphertext) => {
+                let mut frame = Vec::with_capacity(Self::NONCE_LEN + ciphertext.len());
+                frame.extend_from_slice(&nonce_bytes);
+                frame.extend_from_slice(&ciphertext);
+                Some(frame)
+            }
+            Err(e) => {
+                log(&format!("Encryption error: {:?}", e));
+                None
+            }
+        }
+    }
+
+    pub fn decrypt_frame(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let cipher = self.aes_cipher.borrow();
+        let Some(cipher) = cipher.as_ref() else {
+            log("AES cipher not initialized");
+            return None;
+        };
+        if frame.len() < Self::NONCE_LEN {
+            log(&format!("Binary frame too short: {} bytes", frame.len()));
+            return None;
+        }
+
+        let (nonce_bytes, ciphertext) = frame.split_at(Self::NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &[],
+        };
+        match cipher.decrypt(Nonce::from_slice(nonce_bytes), payload) {
+            Ok(decrypted_bytes) => Some(decrypted_bytes),
+            Err(e) => {
+                log(&format!("Decryption failed: {:?}", e));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_the_resume_proof_to_the_key_exchange() {
+        let client = Crypto::new();
+        let server = Crypto::new().public_key_b64();
+        let other_server = Crypto::new().public_key_b64();
+
+        let proof = client.resume_proof("token", &server).unwrap();
+        assert_eq!(proof, client.resume_proof("token", &server).unwrap());
+        assert_ne!(proof, client.resume_proof("token", &other_server).unwrap());
+        assert_ne!(proof, client.resume_proof("other", &server).unwrap());
+        assert_ne!(proof, Crypto::new().resume_proof("token", &server).unwrap());
+        assert!(!proof.contains("token"));
+    }
+
+    #[test]
+    fn names_the_session_without_the_token() {
+        assert_eq!(Crypto::session_id("token"), Crypto::session_id("token"));
+        assert_ne!(Crypto::session_id("token"), Crypto::session_id("other"));
+        assert!(!Crypto::session_id("token").contains("token"));
+    }
+
+    #[test]
+    fn rejects_malformed_public_keys() {
+        let crypto = Crypto::new();
+        assert!(crypto.derive_shared_secret("not base64!").is_err());
+        assert!(crypto.resume_proof("token", "AAAA").is_err());
+    }
 }
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::connection::{ClientConnection, ServerMessage};
+use crate::error::AppError;
+
+pub trait ActionHandler {
+    fn handle(&self, conn: &ClientConnection, message: &ServerMessage) -> Result<(), AppError>;
+}
+
+impl<F> ActionHandler for F
+where
+    F: Fn(&ClientConnection, &ServerMessage) -> Result<(), AppError>,
+{
+    fn handle(&self, conn: &ClientConnection, message: &ServerMessage) -> Result<(), AppError> {
+        self(conn, message)
+    }
+}
+
+// routes each server message to the handlers registered for its action, in registration order
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<&'static str, Vec<Rc<dyn ActionHandler>>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, action: &'static str, handler: impl ActionHandler + 'static) {
+        self.handlers
+            .entry(action)
+            .or_default()
+            .push(Rc::new(handler));
+    }
+
+    // cloned out so a handler may register others while it runs
+    pub fn handlers(
+        &self,
+        message: &ServerMessage,
+    ) -> Result<Vec<Rc<dyn ActionHandler>>, AppError> {
+        match self.handlers.get(message.action()) {
+            Some(handlers) if !handlers.is_empty() => Ok(handlers.clone()),
+            _ => Err(AppError::InvalidState(format!(
+                "No handler registered for action {}",
+                message.action()
+            ))),
+        }
+    }
+}
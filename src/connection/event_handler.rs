@@ -1,13 +1,12 @@
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
-use web_sys::{ErrorEvent, PopStateEvent, WebSocket, Window};
+use web_sys::{AddEventListenerOptions, PopStateEvent, Window};
 
-use crate::VirtualDom;
 use crate::connection::ClientConnection;
-use crate::connection::Crypto;
 use crate::connection::{ClientMessage, MessageHandler, Messaging};
+use crate::connection::{Compression, ConnectionState, Crypto, Encoding, Protocol, Socket};
 use crate::error::AppError;
-use crate::utils::log;
+use crate::utils::{Preferences, log};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -15,95 +14,148 @@ pub struct EventHandler;
 
 impl EventHandler {
     pub fn setup_handlers(conn: &ClientConnection) -> Result<(), AppError> {
-        MessageHandler::setup_message_handler(
-            &conn.ws,
-            &conn.window,
-            conn.vdom.clone(),
-            &conn.crypto,
-        )?;
         Self::setup_popstate_handler(&conn.ws, &conn.window, &conn.crypto)?;
+        Self::setup_preference_handler(&conn.ws, &conn.window, &conn.crypto)?;
+        Self::setup_online_handler(conn)?;
+        Self::setup_push_api(conn)?;
+        Self::setup_socket_handlers(conn)
+    }
+
+    // window.quillion.push(id, data) sends a push action and resolves with the server's reply
+    fn setup_push_api(conn: &ClientConnection) -> Result<(), AppError> {
+        let ws_clone = conn.ws.clone();
+        let window_clone = conn.window.clone();
+        let crypto_clone = conn.crypto.clone();
+
+        let push_callback = Closure::<dyn FnMut(String, JsValue) -> js_sys::Promise>::new(
+            move |id: String, data: JsValue| {
+                let data = js_sys::JSON::stringify(&data)
+                    .ok()
+                    .and_then(|json| json.as_string())
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or(serde_json::Value::Null);
+
+                match Messaging::send_encrypted_message(
+                    &ws_clone,
+                    &ClientMessage::Push { id: &id, data },
+                    &crypto_clone.borrow(),
+                ) {
+                    Some(request_id) => ws_clone.requests.wait(&window_clone, request_id),
+                    None => js_sys::Promise::reject(&js_sys::Error::new("Push could not be sent")),
+                }
+            },
+        );
+
+        let api = js_sys::Reflect::get(&conn.window, &JsValue::from_str("quillion"))
+            .ok()
+            .filter(|api| api.is_object())
+            .unwrap_or_else(|| js_sys::Object::new().into());
+        js_sys::Reflect::set(&api, &JsValue::from_str("push"), push_callback.as_ref())?;
+        js_sys::Reflect::set(&conn.window, &JsValue::from_str("quillion"), &api)?;
+        push_callback.forget();
+        Ok(())
+    }
+
+    // everything bound to a single socket; re-run for each new one after a reconnect
+    fn setup_socket_handlers(conn: &ClientConnection) -> Result<(), AppError> {
+        MessageHandler::setup_message_handler(conn)?;
         Self::setup_error_handler(&conn.ws)?;
-        Self::setup_open_handler(&conn.ws, &conn.crypto)?;
-        Self::setup_close_handler(
-            &conn.ws,
-            &conn.window.clone(),
-            conn.vdom.clone(),
-            &conn.crypto,
-            &conn.ws_gateway,
-        )?;
+        Self::setup_open_handler(conn)?;
+        Self::setup_close_handler(conn)?;
         Ok(())
     }
 
-    fn setup_close_handler(
-        ws: &WebSocket,
-        window: &Window,
-        vdom: Rc<RefCell<Option<VirtualDom>>>,
-        crypto: &Rc<RefCell<Crypto>>,
-        ws_gateway: &str,
-    ) -> Result<(), AppError> {
-        let ws_clone = ws.clone();
-        let window_clone = window.clone();
-        let vdom_clone = vdom.clone();
-        let crypto_clone = crypto.clone();
-        let ws_gateway = ws_gateway.to_string();
-
-        let onclose_callback = Closure::<dyn FnMut()>::new(move || {
-            let window_clone = window_clone.clone();
-            let vdom = vdom_clone.clone();
-            let crypto = crypto_clone.clone();
-            let ws_gateway = ws_gateway.clone();
-
-            let window_value = window_clone.clone();
-
-            let _ = window_clone.set_timeout_with_callback_and_timeout_and_arguments_0(
-                &Closure::once_into_js(move || {
-                    let new_ws = match WebSocket::new(&ws_gateway) {
-                        Ok(ws) => ws,
-                        Err(_e) => {
-                            return ();
-                        }
-                    };
-
-                    if let Err(_e) = Self::setup_open_handler(&new_ws, &crypto) {
-                        return ();
-                    }
-
-                    if let Err(_e) = MessageHandler::setup_message_handler(
-                        &new_ws,
-                        &window_value,
-                        vdom.clone(),
-                        &crypto,
-                    ) {
-                        return ();
-                    }
-
-                    if let Err(_e) = Self::setup_error_handler(&new_ws) {
-                        return ();
-                    }
-
-                    if let Err(_e) = Self::setup_close_handler(
-                        &new_ws,
-                        &window_value,
-                        vdom,
-                        &crypto,
-                        &ws_gateway,
-                    ) {
-                        return ();
-                    }
-                })
-                .as_ref()
-                .unchecked_ref(),
-                1000,
-            );
+    fn setup_close_handler(conn: &ClientConnection) -> Result<(), AppError> {
+        let conn_clone = conn.clone();
+
+        conn.ws.current().on_close(Box::new(move || {
+            // the session key died with the socket; the next one runs a fresh key exchange
+            conn_clone.crypto.borrow().clear_session();
+            conn_clone.ws.set_ready(false);
+            let queued = conn_clone.ws.outbox.borrow().request_ids();
+            conn_clone
+                .ws
+                .requests
+                .reject_except(&queued, "Connection closed before the reply");
+            conn_clone.transports.closed();
+            conn_clone.heartbeat.stop(&conn_clone.window);
+            if conn_clone.reconnector.is_halted() {
+                return;
+            }
+            Self::schedule_reconnect(&conn_clone);
+        }));
+        Ok(())
+    }
+
+    fn schedule_reconnect(conn: &ClientConnection) {
+        let conn_clone = conn.clone();
+        let scheduled = conn
+            .reconnector
+            .schedule(&conn.window, move || Self::reconnect(&conn_clone));
+
+        conn.status.set(if !scheduled {
+            ConnectionState::Failed
+        } else if conn.window.navigator().on_line() {
+            ConnectionState::Reconnecting
+        } else {
+            ConnectionState::Offline
+        });
+    }
+
+    fn setup_online_handler(conn: &ClientConnection) -> Result<(), AppError> {
+        let conn_clone = conn.clone();
+
+        let ononline_callback = Closure::<dyn FnMut()>::new(move || {
+            let status = &conn_clone.status;
+            if matches!(
+                status.get(),
+                ConnectionState::Offline | ConnectionState::Failed
+            ) {
+                status.set(ConnectionState::Reconnecting);
+            }
+            conn_clone
+                .reconnector
+                .retry_now(&conn_clone.window, || Self::reconnect(&conn_clone));
         });
 
-        ws_clone.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-        onclose_callback.forget();
+        let status = conn.status.clone();
+        let onoffline_callback = Closure::<dyn FnMut()>::new(move || {
+            if status.get() == ConnectionState::Reconnecting {
+                status.set(ConnectionState::Offline);
+            }
+        });
+
+        conn.window.add_event_listener_with_callback(
+            "online",
+            ononline_callback.as_ref().unchecked_ref(),
+        )?;
+        conn.window.add_event_listener_with_callback(
+            "offline",
+            onoffline_callback.as_ref().unchecked_ref(),
+        )?;
+        ononline_callback.forget();
+        onoffline_callback.forget();
         Ok(())
     }
 
+    fn reconnect(conn: &ClientConnection) {
+        let transport = match conn.transports.connect() {
+            Ok(transport) => transport,
+            Err(e) => {
+                log(&format!("Failed to open transport: {}", e));
+                Self::schedule_reconnect(conn);
+                return;
+            }
+        };
+
+        conn.ws.replace(transport);
+        if let Err(e) = Self::setup_socket_handlers(conn) {
+            log(&format!("Failed to set up reconnected socket: {}", e));
+        }
+    }
+
     fn setup_popstate_handler(
-        ws: &WebSocket,
+        ws: &Socket,
         window: &Window,
         crypto: &Rc<RefCell<Crypto>>,
     ) -> Result<(), AppError> {
@@ -128,32 +180,102 @@ impl EventHandler {
         Ok(())
     }
 
-    fn setup_error_handler(ws: &WebSocket) -> Result<(), AppError> {
+    fn setup_preference_handler(
+        ws: &Socket,
+        window: &Window,
+        crypto: &Rc<RefCell<Crypto>>,
+    ) -> Result<(), AppError> {
         let ws_clone = ws.clone();
-        let onerror_callback = Closure::<dyn FnMut(_)>::new(move |e: ErrorEvent| {
-            log(&format!("WebSocket error: {:?}", e));
+        let window_clone = window.clone();
+        let crypto_clone = crypto.clone();
+        let last_reported = RefCell::new(Preferences::from_window(window));
+
+        let report: Rc<dyn Fn()> = Rc::new(move || {
+            let preferences = Preferences::from_window(&window_clone);
+            if *last_reported.borrow() == preferences {
+                return;
+            }
+            *last_reported.borrow_mut() = preferences.clone();
+            Messaging::send_encrypted_message(
+                &ws_clone,
+                &ClientMessage::Preferences { preferences },
+                &crypto_clone.borrow(),
+            );
         });
-        ws_clone.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-        onerror_callback.forget();
+
+        for query in Preferences::WATCHED_QUERIES {
+            if let Some(list) = window.match_media(query)? {
+                let report = report.clone();
+                let onchange_callback = Closure::<dyn FnMut()>::new(move || report());
+                list.add_event_listener_with_callback(
+                    "change",
+                    onchange_callback.as_ref().unchecked_ref(),
+                )?;
+                onchange_callback.forget();
+            }
+        }
+
+        Self::watch_pixel_ratio(window, report);
         Ok(())
     }
 
-    fn setup_open_handler(ws: &WebSocket, crypto: &Rc<RefCell<Crypto>>) -> Result<(), AppError> {
-        let ws_clone = ws.clone();
-        let crypto_clone = crypto.clone();
-        let public_key_b64 = crypto_clone.borrow().public_key_b64();
+    // a resolution query only matches the current ratio, so it is re-armed after every change
+    fn watch_pixel_ratio(window: &Window, report: Rc<dyn Fn()>) {
+        let Ok(Some(list)) = window.match_media(&Preferences::pixel_ratio_query(window)) else {
+            return;
+        };
+
+        let window_clone = window.clone();
+        let onchange_callback = Closure::once_into_js(move || {
+            report();
+            Self::watch_pixel_ratio(&window_clone, report);
+        });
+
+        let options = AddEventListenerOptions::new();
+        options.set_once(true);
+        let _ = list.add_event_listener_with_callback_and_add_event_listener_options(
+            "change",
+            onchange_callback.unchecked_ref(),
+            &options,
+        );
+    }
+
+    fn setup_error_handler(ws: &Socket) -> Result<(), AppError> {
+        let transport = ws.current();
+        let kind = transport.kind();
+        transport.on_error(Box::new(move |e: String| {
+            log(&format!("{:?} error: {}", kind, e));
+        }));
+        Ok(())
+    }
 
-        let onopen_callback = Closure::<dyn FnMut()>::new(move || {
+    fn setup_open_handler(conn: &ClientConnection) -> Result<(), AppError> {
+        let ws_clone = conn.ws.clone();
+        let reconnector = conn.reconnector.clone();
+        let transports = conn.transports.clone();
+        let status = conn.status.clone();
+        let public_key_b64 = conn.crypto.borrow().public_key_b64();
+        let encodings = Encoding::offered(conn.ws.current().supports_binary());
+
+        conn.ws.current().on_open(Box::new(move || {
+            let Some(window) = web_sys::window() else {
+                return;
+            };
+            reconnector.reset();
+            transports.opened();
+            status.set(ConnectionState::Handshaking);
             Messaging::send_message(
                 &ws_clone,
                 &ClientMessage::PublicKey {
                     key: public_key_b64.clone(),
+                    preferences: Preferences::from_window(&window),
+                    protocol: Protocol::VERSION,
+                    features: Protocol::FEATURES.to_vec(),
+                    encodings: encodings.clone(),
+                    compression: Compression::offered(),
                 },
             );
-        });
-
-        ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-        onopen_callback.forget();
+        }));
         Ok(())
     }
 }
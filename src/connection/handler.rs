@@ -1,133 +1,417 @@
 use crate::connection::Messaging;
-use crate::connection::{ClientMessage, ServerMessage};
+use crate::connection::{ClientMessage, Envelope, Incoming, ServerMessage};
+use crate::error::AppError;
 use std::cell::RefCell;
 use std::rc::Rc;
-use wasm_bindgen::JsCast;
-use wasm_bindgen::prelude::*;
-use web_sys::{MessageEvent, WebSocket};
 
-use crate::connection::Crypto;
+use crate::connection::{
+    ClientConnection, Compression, ConnectionState, Crypto, Dispatcher, Encoding, Frame, Protocol,
+    Socket, Subscriptions, Uploads,
+};
 use crate::utils::format_wasm_traceback;
 use crate::utils::formatter::log;
-use crate::vdom::VirtualDom;
 
 pub struct MessageHandler;
 
 impl MessageHandler {
-    pub fn setup_message_handler(
-        ws: &WebSocket,
-        window: &web_sys::Window,
-        vdom_ref: Rc<RefCell<Option<VirtualDom>>>,
-        crypto: &Rc<RefCell<Crypto>>,
-    ) -> Result<(), crate::error::AppError> {
+    pub fn setup_message_handler(conn: &ClientConnection) -> Result<(), AppError> {
         // trash
 
-        let ws_clone = ws.clone();
-        let window_clone = window.clone();
-        let vdom_ref = vdom_ref.clone();
-        let crypto_clone = crypto.clone();
-
-        let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
-            let ws_clone_for_error = ws_clone.clone();
-            let crypto_for_msg_handler = crypto_clone.clone();
-
-            if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
-                let json_str: String = txt.into();
-                match serde_json::from_str::<ServerMessage>(&json_str) {
-                    Ok(msg) => {
-                        if let Some(server_public_key_b64) = &msg.server_public_key {
-                            if crypto_for_msg_handler
-                                .borrow()
-                                .derive_shared_secret(server_public_key_b64)
-                                .is_ok()
-                            {
-                                let path = window_clone
-                                    .location()
-                                    .pathname()
-                                    .unwrap_or_else(|_| "/".to_string());
-                                Messaging::send_encrypted_message(
-                                    &ws_clone,
-                                    &ClientMessage::Navigate { path: &path },
-                                    &crypto_for_msg_handler.borrow(),
-                                );
-                            }
-                            return;
-                        }
-
-                        if let (Some(encrypted_payload_b64), Some(nonce_b64)) =
-                            (&msg.encrypted_payload, &msg.nonce)
-                        {
-                            if let Some(decrypted_str) = crypto_for_msg_handler
-                                .borrow()
-                                .decrypt(encrypted_payload_b64, nonce_b64)
-                            {
-                                match serde_json::from_str::<ServerMessage>(&decrypted_str) {
-                                    Ok(inner_msg) => {
-                                        if let Some(vdom) = &mut *vdom_ref.borrow_mut() {
-                                            if inner_msg.action == "render_page" {
-                                                vdom.render_page(
-                                                    &window_clone,
-                                                    &ws_clone,
-                                                    &inner_msg.content,
-                                                    &inner_msg.path,
-                                                    &inner_msg.css_rules,
-                                                );
-                                            } else if inner_msg.action == "redirect" {
-                                                if let Some(url) = &inner_msg.url {
-                                                    if let Some(win) = web_sys::window() {
-                                                        let _ = win.location().set_href(url);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                    Err(e) => {
-                                        let formatted_error = format_wasm_traceback(&e.to_string());
-                                        log(&e.to_string());
-                                        Messaging::send_encrypted_message(
-                                            &ws_clone,
-                                            &ClientMessage::ClientError {
-                                                error: formatted_error,
-                                            },
-                                            &crypto_for_msg_handler.borrow(),
-                                        );
-                                    }
-                                }
-                            }
-                            return;
-                        }
-
-                        if msg.action == "render_page" {
-                            if let Some(vdom) = &mut *vdom_ref.borrow_mut() {
-                                vdom.render_page(
-                                    &window_clone,
-                                    &ws_clone,
-                                    &msg.content,
-                                    &msg.path,
-                                    &msg.css_rules,
-                                );
-                            }
-                        }
+        let conn_clone = conn.clone();
+        conn.ws
+            .current()
+            .on_message(Box::new(move |frame: Frame| match frame {
+                Frame::Text(json_str) => Self::handle_text(&conn_clone, &json_str),
+                Frame::Binary(bytes) => Self::handle_binary(&conn_clone, &bytes),
+            }));
+        Ok(())
+    }
+
+    fn handle_text(conn: &ClientConnection, json_str: &str) {
+        let envelope = match serde_json::from_str::<Envelope>(json_str) {
+            Ok(envelope) => envelope,
+            Err(e) => return Self::report_error(conn, &e.to_string()),
+        };
+
+        // sent in place of the handshake reply by servers that refuse this version outright
+        if envelope.action.as_deref() == Some("protocol_mismatch") {
+            Self::protocol_mismatch(conn, envelope.protocol.unwrap_or_default());
+            return;
+        }
+
+        if let Some(server_public_key_b64) = &envelope.server_public_key {
+            Self::complete_handshake(conn, server_public_key_b64, &envelope);
+            return;
+        }
+
+        if let (Some(encrypted_payload_b64), Some(nonce_b64)) =
+            (&envelope.encrypted_payload, &envelope.nonce)
+        {
+            let decrypted = conn
+                .crypto
+                .borrow()
+                .decrypt_bytes(encrypted_payload_b64, nonce_b64);
+            if let Some(bytes) = decrypted {
+                let inflated = match envelope.compressed {
+                    Some(true) => conn.ws.compression().decompress(&bytes),
+                    _ => Ok(bytes),
+                };
+                match inflated.and_then(|bytes| {
+                    serde_json::from_slice::<Incoming>(&bytes).map_err(AppError::SerializationError)
+                }) {
+                    Ok(incoming) => Self::dispatch(conn, incoming),
+                    Err(e) => Self::report_error(conn, &e.to_string()),
+                }
+            }
+            return;
+        }
+
+        // a page rendered before the handshake is the only thing trusted in the clear
+        if let Ok(Incoming {
+            message:
+                ServerMessage::RenderPage {
+                    content,
+                    path,
+                    css_rules,
+                },
+            ..
+        }) = serde_json::from_str::<Incoming>(json_str)
+            && let Some(vdom) = &mut *conn.vdom.borrow_mut()
+        {
+            vdom.render_page(&conn.window, &conn.ws, &content, &path, &css_rules);
+        }
+    }
+
+    fn handle_binary(conn: &ClientConnection, frame: &[u8]) {
+        let decrypted = conn.crypto.borrow().decrypt_frame(frame);
+        if let Some(bytes) = decrypted {
+            match conn
+                .ws
+                .compression()
+                .unpack(bytes)
+                .and_then(|bytes| conn.ws.encoding().decode::<Incoming>(&bytes))
+            {
+                Ok(incoming) => Self::dispatch(conn, incoming),
+                Err(e) => Self::report_error(conn, &e.to_string()),
+            }
+        }
+    }
+
+    fn complete_handshake(conn: &ClientConnection, server_public_key_b64: &str, msg: &Envelope) {
+        if Protocol::check(msg.protocol).is_err() {
+            Self::protocol_mismatch(conn, msg.protocol.unwrap_or_default());
+            return;
+        }
+        Protocol::agreed(&conn.window);
+
+        // left half-open the connection would sit in Handshaking; closing it lets the close
+        // handler schedule the reconnect
+        if let Err(e) = conn
+            .crypto
+            .borrow()
+            .derive_shared_secret(server_public_key_b64)
+        {
+            Self::report_error(conn, &e.to_string());
+            conn.ws.current().close();
+            return;
+        }
+
+        let encoding = msg
+            .encoding
+            .as_deref()
+            .and_then(Encoding::from_name)
+            .filter(|encoding| !encoding.is_binary() || conn.ws.current().supports_binary())
+            .unwrap_or_default();
+        conn.ws.set_encoding(encoding);
+        conn.ws.set_compression(
+            msg.compression
+                .as_deref()
+                .and_then(Compression::from_name)
+                .unwrap_or_default(),
+        );
+
+        conn.ws
+            .set_features(msg.features.clone().unwrap_or_default());
+
+        conn.status.set(ConnectionState::Connected);
+        conn.heartbeat.start(&conn.window, &conn.ws, &conn.crypto);
+        let path = Self::current_path(&conn.window);
+        // a server that does not list resume would never answer it and leave the outbox held
+        let resume = conn
+            .session
+            .token()
+            .filter(|_| conn.ws.has_feature("resume"))
+            .and_then(|token| {
+                let proof = conn
+                    .crypto
+                    .borrow()
+                    .resume_proof(&token, server_public_key_b64)
+                    .ok()?;
+                Some((Crypto::session_id(&token), proof))
+            });
+        match resume {
+            // the token itself never leaves the page; the proof only holds for this handshake
+            Some((session, proof)) => {
+                Messaging::send_encrypted_message(
+                    &conn.ws,
+                    &ClientMessage::Resume {
+                        session,
+                        proof,
+                        path: &path,
+                    },
+                    &conn.crypto.borrow(),
+                );
+            }
+            None => Self::start_session(
+                &conn.ws,
+                &conn.window,
+                &conn.crypto.borrow(),
+                &conn.subscriptions,
+            ),
+        }
+    }
+
+    // an action without a handler is reported back, so protocol drift does not go unnoticed.
+    // the request it answers is settled either way, and rejected when handling failed
+    fn dispatch(conn: &ClientConnection, incoming: Incoming) {
+        let Incoming {
+            request_id,
+            message,
+        } = incoming;
+
+        let result = if message.is_malformed() {
+            Err(AppError::InvalidState(format!(
+                "Malformed {} message",
+                message.action()
+            )))
+        } else {
+            let handlers = conn.dispatcher.borrow().handlers(&message);
+            handlers.and_then(|handlers| {
+                handlers
+                    .iter()
+                    .try_for_each(|handler| handler.handle(conn, &message))
+            })
+        };
+        if let Err(e) = &result {
+            Self::report_error(conn, &e.to_string());
+        }
+
+        if let Some(request_id) = request_id {
+            if let Some(vdom) = &*conn.vdom.borrow() {
+                vdom.settle_request(request_id);
+            }
+            match (&result, &message) {
+                (Err(e), _) => conn.ws.requests.reject(request_id, &e.to_string()),
+                (_, ServerMessage::Error { error }) => conn
+                    .ws
+                    .requests
+                    .reject(request_id, error.as_deref().unwrap_or("Request failed")),
+                (_, ServerMessage::Ack { data }) => {
+                    conn.ws.requests.resolve(request_id, data.as_ref())
+                }
+                _ => conn.ws.requests.resolve(request_id, None),
+            }
+        }
+    }
+
+    pub fn register_defaults(dispatcher: &mut Dispatcher) {
+        dispatcher.register(
+            "render_page",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                let ServerMessage::RenderPage {
+                    content,
+                    path,
+                    css_rules,
+                } = message
+                else {
+                    return Ok(());
+                };
+                if let Some(vdom) = &mut *conn.vdom.borrow_mut() {
+                    if let Some(path) = path {
+                        conn.subscriptions.borrow_mut().enter_page(path);
                     }
-                    Err(e) => {
-                        let err = e.to_string();
-                        let formatted_error = format_wasm_traceback(&err);
-                        log(&err);
-                        Messaging::send_encrypted_message(
-                            &ws_clone_for_error,
-                            &ClientMessage::ClientError {
-                                error: formatted_error,
-                            },
-                            &crypto_for_msg_handler.borrow(),
-                        );
+                    vdom.render_page(&conn.window, &conn.ws, content, path, css_rules);
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "redirect",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Redirect { url } = message {
+                    conn.window.location().set_href(url)?;
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "pong",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Pong { sent_at } = message {
+                    conn.heartbeat.pong(&conn.window, *sent_at);
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "session",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Session { session_token } = message {
+                    conn.session.set(session_token);
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "resume",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Resume { session_token } = message {
+                    if let Some(token) = session_token {
+                        conn.session.set(token);
+                    }
+                    conn.ws.set_ready(true);
+                    Messaging::flush_outbox(&conn.ws, &conn.crypto.borrow());
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "reset",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Reset { session_token } = message {
+                    match session_token {
+                        Some(token) => conn.session.set(token),
+                        None => conn.session.clear(),
                     }
+                    Self::start_session(
+                        &conn.ws,
+                        &conn.window,
+                        &conn.crypto.borrow(),
+                        &conn.subscriptions,
+                    );
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "subscribe",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                let ServerMessage::Subscribe {
+                    id,
+                    event,
+                    target,
+                    throttle,
+                } = message
+                else {
+                    return Ok(());
+                };
+                conn.subscriptions.borrow_mut().subscribe(
+                    &conn.ws,
+                    &conn.window,
+                    &conn.crypto,
+                    target.as_deref().unwrap_or("window"),
+                    event,
+                    id,
+                    *throttle,
+                )
+            },
+        );
+
+        dispatcher.register(
+            "unsubscribe",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::Unsubscribe { id, event } = message {
+                    conn.subscriptions
+                        .borrow_mut()
+                        .unsubscribe(id, event.as_deref());
                 }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "upload_accept",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::UploadAccept { upload_id } = message
+                    && let Err(e) =
+                        Uploads::accept(&conn.uploads, &conn.ws, &conn.crypto, upload_id)
+                {
+                    log(&e.to_string());
+                }
+                Ok(())
+            },
+        );
+
+        dispatcher.register(
+            "upload_reject",
+            |conn: &ClientConnection, message: &ServerMessage| {
+                if let ServerMessage::UploadReject { upload_id, error } = message {
+                    conn.uploads
+                        .borrow_mut()
+                        .reject(upload_id, error.as_deref());
+                }
+                Ok(())
+            },
+        );
+
+        // both settle their request in dispatch and carry nothing else
+        dispatcher.register("ack", |_: &ClientConnection, _: &ServerMessage| Ok(()));
+        dispatcher.register("error", |_: &ClientConnection, message: &ServerMessage| {
+            if let ServerMessage::Error { error } = message {
+                log(&format!(
+                    "Request failed: {}",
+                    error.as_deref().unwrap_or("unknown error")
+                ));
             }
+            Ok(())
         });
+    }
 
-        ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-        onmessage_callback.forget();
-        Ok(())
+    // nothing sent under a protocol the other side cannot read is worth retrying
+    fn protocol_mismatch(conn: &ClientConnection, server: u32) {
+        conn.reconnector.halt(&conn.window);
+        if !Protocol::handle_mismatch(&conn.window, server) {
+            conn.status.set(ConnectionState::Incompatible);
+        }
+        conn.ws.current().close();
+    }
+
+    fn report_error(conn: &ClientConnection, error: &str) {
+        let formatted_error = format_wasm_traceback(error);
+        log(error);
+        Messaging::send_encrypted_message(
+            &conn.ws,
+            &ClientMessage::ClientError {
+                error: formatted_error,
+            },
+            &conn.crypto.borrow(),
+        );
+    }
+
+    // a fresh server session knows nothing about this page and resubscribes after the navigate
+    fn start_session(
+        ws: &Socket,
+        window: &web_sys::Window,
+        crypto: &Crypto,
+        subscriptions: &Rc<RefCell<Subscriptions>>,
+    ) {
+        subscriptions.borrow_mut().clear();
+        ws.set_ready(true);
+        let path = Self::current_path(window);
+        Messaging::send_encrypted_message(ws, &ClientMessage::Navigate { path: &path }, crypto);
+        Messaging::flush_outbox(ws, crypto);
+    }
+
+    fn current_path(window: &web_sys::Window) -> String {
+        window
+            .location()
+            .pathname()
+            .unwrap_or_else(|_| "/".to_string())
     }
 }
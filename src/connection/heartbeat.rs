@@ -0,0 +1,128 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::Window;
+
+use crate::connection::{ClientMessage, Crypto, Messaging, Socket};
+use crate::utils::{EventDispatcher, MetaConfig, log};
+
+// keeps idle sockets alive through proxies and notices dead ones before the next send does
+pub struct Heartbeat {
+    enabled: bool,
+    interval_ms: u32,
+    timeout_ms: u32,
+    report_latency: bool,
+    interval: Cell<Option<i32>>,
+    // owned here rather than forgotten, so every reconnect does not leak one
+    tick: RefCell<Option<Closure<dyn FnMut()>>>,
+    deadline: Cell<Option<i32>>,
+    latency: Cell<Option<f64>>,
+}
+
+impl Heartbeat {
+    pub const LATENCY_ATTR: &'static str = "data-quillion-latency";
+    pub const LATENCY_EVENT: &'static str = "quillion:latency";
+
+    pub fn new(config: &MetaConfig) -> Self {
+        Self {
+            enabled: config.heartbeat,
+            interval_ms: config.heartbeat_interval_ms,
+            timeout_ms: config.heartbeat_timeout_ms,
+            report_latency: config.report_latency,
+            interval: Cell::new(None),
+            tick: RefCell::new(None),
+            deadline: Cell::new(None),
+            latency: Cell::new(None),
+        }
+    }
+
+    pub fn latency(&self) -> Option<f64> {
+        self.latency.get()
+    }
+
+    // a server that never answers pings would have its socket closed on every timeout, so
+    // the heartbeat only runs when the server lists it or the page opts in
+    pub fn start(self: &Rc<Self>, window: &Window, ws: &Socket, crypto: &Rc<RefCell<Crypto>>) {
+        self.stop(window);
+        if self.interval_ms == 0 || !(self.enabled || ws.has_feature("heartbeat")) {
+            return;
+        }
+
+        let this = self.clone();
+        let window_clone = window.clone();
+        let ws = ws.clone();
+        let crypto = crypto.clone();
+        let tick = Closure::<dyn FnMut()>::new(move || this.ping(&window_clone, &ws, &crypto));
+
+        match window.set_interval_with_callback_and_timeout_and_arguments_0(
+            tick.as_ref().unchecked_ref(),
+            self.interval_ms as i32,
+        ) {
+            Ok(handle) => self.interval.set(Some(handle)),
+            Err(e) => log(&format!("Failed to start heartbeat: {:?}", e)),
+        }
+        *self.tick.borrow_mut() = Some(tick);
+    }
+
+    pub fn stop(&self, window: &Window) {
+        if let Some(handle) = self.interval.take() {
+            window.clear_interval_with_handle(handle);
+        }
+        // the closure holds this heartbeat, so dropping it also breaks that cycle
+        self.tick.borrow_mut().take();
+        if let Some(handle) = self.deadline.take() {
+            window.clear_timeout_with_handle(handle);
+        }
+    }
+
+    pub fn pong(&self, window: &Window, sent_at: f64) {
+        if let Some(handle) = self.deadline.take() {
+            window.clear_timeout_with_handle(handle);
+        }
+
+        let latency = js_sys::Date::now() - sent_at;
+        self.latency.set(Some(latency));
+
+        let Some(root) = window.document().and_then(|d| d.document_element()) else {
+            return;
+        };
+        let _ = root.set_attribute(Self::LATENCY_ATTR, &format!("{}", latency.round()));
+        EventDispatcher::dispatch(
+            &root,
+            Self::LATENCY_EVENT,
+            &serde_json::json!({ "latency": latency }),
+        );
+    }
+
+    fn ping(&self, window: &Window, ws: &Socket, crypto: &Rc<RefCell<Crypto>>) {
+        // still waiting on the previous pong; its deadline decides
+        if self.deadline.get().is_some() || !ws.is_open() {
+            return;
+        }
+
+        Messaging::send_encrypted_message(
+            ws,
+            &ClientMessage::Ping {
+                sent_at: js_sys::Date::now(),
+                latency: self.latency.get().filter(|_| self.report_latency),
+            },
+            &crypto.borrow(),
+        );
+
+        // closing hands the dead socket to the regular reconnect path
+        let ws = ws.clone();
+        let timeout_ms = self.timeout_ms;
+        let expired = Closure::once_into_js(move || {
+            log(&format!("No pong within {} ms, closing socket", timeout_ms));
+            ws.current().close();
+        });
+        match window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            expired.unchecked_ref(),
+            self.timeout_ms as i32,
+        ) {
+            Ok(handle) => self.deadline.set(Some(handle)),
+            Err(e) => log(&format!("Failed to arm heartbeat timeout: {:?}", e)),
+        }
+    }
+}
@@ -1,16 +1,34 @@
 use serde::Serialize;
 
+use crate::utils::Preferences;
+
 #[derive(Serialize, Debug)]
 #[serde(tag = "action")]
 pub enum ClientMessage<'a> {
     #[serde(rename = "callback")]
-    Callback { id: &'a str },
+    Callback {
+        id: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<u64>,
+    },
     #[serde(rename = "navigate")]
     Navigate { path: &'a str },
     #[serde(rename = "public_key")]
-    PublicKey { key: String },
+    PublicKey {
+        key: String,
+        preferences: Preferences,
+        protocol: u32,
+        features: Vec<&'static str>,
+        encodings: Vec<&'static str>,
+        compression: Vec<&'static str>,
+    },
     #[serde(rename = "encrypted_message")]
-    EncryptedMessage { data: String, nonce: String },
+    EncryptedMessage {
+        data: String,
+        nonce: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        compressed: Option<bool>,
+    },
     #[serde(rename = "client_error")]
     ClientError { error: String },
     #[serde(rename = "event_callback")]
@@ -18,5 +36,66 @@ pub enum ClientMessage<'a> {
         id: &'a str,
         event_type: String,
         event_data: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        validity: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<u64>,
+    },
+    #[serde(rename = "upload_start")]
+    UploadStart {
+        upload_id: String,
+        field: String,
+        name: String,
+        mime_type: String,
+        size: f64,
+    },
+    #[serde(rename = "upload_chunk")]
+    UploadChunk {
+        upload_id: String,
+        offset: f64,
+        data: String,
     },
+    #[serde(rename = "preferences")]
+    Preferences { preferences: Preferences },
+    #[serde(rename = "upload_complete")]
+    UploadComplete { upload_id: String },
+    #[serde(rename = "resume")]
+    Resume {
+        session: String,
+        proof: String,
+        path: &'a str,
+    },
+    #[serde(rename = "push")]
+    Push {
+        id: &'a str,
+        data: serde_json::Value,
+    },
+    #[serde(rename = "ping")]
+    Ping {
+        sent_at: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        latency: Option<f64>,
+    },
+}
+
+impl ClientMessage<'_> {
+    pub fn request_id(&self) -> Option<u64> {
+        match self {
+            Self::Callback { request_id, .. } | Self::EventCallback { request_id, .. } => {
+                *request_id
+            }
+            _ => None,
+        }
+    }
+
+    // the handshake, heartbeats and error reports are not answered by the server
+    pub fn is_action(&self) -> bool {
+        !matches!(
+            self,
+            Self::PublicKey { .. }
+                | Self::EncryptedMessage { .. }
+                | Self::Ping { .. }
+                | Self::ClientError { .. }
+        )
+    }
 }
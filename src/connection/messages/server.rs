@@ -1,17 +1,13 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-use crate::vdom::ElementContent;
+use crate::vdom::{CssRules, ElementContent};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ServerMessage {
-    pub action: String,
-    #[serde(default)]
-    pub content: Vec<ElementContent>,
+// the outer text frame: a handshake reply, an encrypted message, or one of the few
+// messages a server sends before a key exists
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Envelope {
     #[serde(default)]
-    pub path: Option<String>,
-    #[serde(default)]
-    pub css_rules: Option<HashMap<String, HashMap<String, String>>>,
+    pub action: Option<String>,
     #[serde(default)]
     pub server_public_key: Option<String>,
     #[serde(default)]
@@ -19,5 +15,208 @@ pub struct ServerMessage {
     #[serde(default)]
     pub nonce: Option<String>,
     #[serde(default)]
-    pub url: Option<String>,
+    pub compressed: Option<bool>,
+    #[serde(default)]
+    pub encoding: Option<String>,
+    #[serde(default)]
+    pub compression: Option<String>,
+    #[serde(default)]
+    pub protocol: Option<u32>,
+    #[serde(default)]
+    pub features: Option<Vec<String>>,
+}
+
+// any server message, with the id of the client request it answers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Incoming {
+    #[serde(default)]
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ServerMessage {
+    RenderPage {
+        #[serde(default)]
+        content: Vec<ElementContent>,
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        css_rules: Option<CssRules>,
+    },
+    Redirect {
+        url: String,
+    },
+    Pong {
+        sent_at: f64,
+    },
+    Session {
+        session_token: String,
+    },
+    Resume {
+        #[serde(default)]
+        session_token: Option<String>,
+    },
+    Reset {
+        #[serde(default)]
+        session_token: Option<String>,
+    },
+    Subscribe {
+        id: String,
+        event: String,
+        #[serde(default)]
+        target: Option<String>,
+        #[serde(default)]
+        throttle: Option<u32>,
+    },
+    Unsubscribe {
+        id: String,
+        #[serde(default)]
+        event: Option<String>,
+    },
+    UploadAccept {
+        upload_id: String,
+    },
+    UploadReject {
+        upload_id: String,
+        #[serde(default)]
+        error: Option<String>,
+    },
+    Ack {
+        #[serde(default)]
+        data: Option<serde_json::Value>,
+    },
+    Error {
+        #[serde(default)]
+        error: Option<String>,
+    },
+    // an action this client has no variant for, or a known one whose fields did not parse;
+    // handlers registered for the action receive the rest of the message as sent
+    #[serde(untagged)]
+    Unknown {
+        action: String,
+        #[serde(flatten)]
+        raw: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+// the wire name of every known variant, kept next to the list of known actions
+macro_rules! server_actions {
+    ($($variant:ident => $action:literal),* $(,)?) => {
+        impl ServerMessage {
+            pub const ACTIONS: &[&str] = &[$($action),*];
+
+            pub fn action(&self) -> &str {
+                match self {
+                    $(Self::$variant { .. } => $action,)*
+                    Self::Unknown { action, .. } => action,
+                }
+            }
+        }
+    };
+}
+
+server_actions! {
+    RenderPage => "render_page",
+    Redirect => "redirect",
+    Pong => "pong",
+    Session => "session",
+    Resume => "resume",
+    Reset => "reset",
+    Subscribe => "subscribe",
+    Unsubscribe => "unsubscribe",
+    UploadAccept => "upload_accept",
+    UploadReject => "upload_reject",
+    Ack => "ack",
+    Error => "error",
+}
+
+impl ServerMessage {
+    // a known action that still landed in Unknown failed to parse
+    pub fn is_malformed(&self) -> bool {
+        matches!(self, Self::Unknown { action, .. } if Self::ACTIONS.contains(&action.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_actions_with_their_request_id() {
+        let incoming: Incoming =
+            serde_json::from_str(r#"{"action": "ack", "request_id": 7, "data": {"ok": true}}"#)
+                .unwrap();
+        assert_eq!(incoming.request_id, Some(7));
+        assert!(matches!(
+            incoming.message,
+            ServerMessage::Ack { data: Some(_) }
+        ));
+    }
+
+    #[test]
+    fn keeps_unknown_actions_and_their_request_id() {
+        let incoming: Incoming =
+            serde_json::from_str(r#"{"action": "toast", "request_id": 3, "text": "hi"}"#).unwrap();
+        assert_eq!(incoming.request_id, Some(3));
+        assert_eq!(incoming.message.action(), "toast");
+        assert!(!incoming.message.is_malformed());
+        let ServerMessage::Unknown { raw, .. } = incoming.message else {
+            panic!("expected an unknown message");
+        };
+        assert_eq!(raw["text"], "hi");
+    }
+
+    #[test]
+    fn marks_known_actions_that_fail_to_parse() {
+        let incoming: Incoming =
+            serde_json::from_str(r#"{"action": "redirect", "request_id": 1}"#).unwrap();
+        assert_eq!(incoming.request_id, Some(1));
+        assert!(incoming.message.is_malformed());
+    }
+
+    #[test]
+    fn lists_the_wire_name_of_every_variant() {
+        for action in ServerMessage::ACTIONS {
+            let message: ServerMessage = serde_json::from_value(serde_json::json!({
+                "action": action,
+                "url": "/",
+                "sent_at": 0.0,
+                "session_token": "token",
+                "id": "id",
+                "event": "event",
+                "upload_id": "upload",
+            }))
+            .unwrap();
+            assert!(
+                !matches!(message, ServerMessage::Unknown { .. }),
+                "{action}"
+            );
+            assert_eq!(message.action(), *action);
+        }
+    }
+
+    #[test]
+    fn keeps_legacy_css_rules_in_document_order() {
+        let incoming: Incoming = serde_json::from_str(
+            r#"{"action": "render_page", "css_rules": {".z": {"color": "red"}, ".a": {"top": "0", "color": "blue"}}}"#,
+        )
+        .unwrap();
+        let ServerMessage::RenderPage {
+            css_rules: Some(CssRules::Legacy(rules)),
+            ..
+        } = incoming.message
+        else {
+            panic!("expected legacy rules");
+        };
+        assert_eq!(rules.keys().collect::<Vec<_>>(), [".z", ".a"]);
+        assert_eq!(rules[".a"].keys().collect::<Vec<_>>(), ["top", "color"]);
+    }
+
+    #[test]
+    fn requires_an_action() {
+        assert!(serde_json::from_str::<Incoming>(r#"{"request_id": 1}"#).is_err());
+    }
 }
@@ -1,49 +1,154 @@
-use web_sys::WebSocket;
-
 use crate::connection::ClientMessage;
+use crate::connection::Compression;
 use crate::connection::Crypto;
+use crate::connection::Outbox;
+use crate::connection::Socket;
 use crate::utils::formatter::log;
 
 pub struct Messaging;
 
 impl Messaging {
-    pub fn send_message(ws: &WebSocket, message: &ClientMessage) {
+    pub fn send_message(ws: &Socket, message: &ClientMessage) -> bool {
         match serde_json::to_string(message) {
-            Ok(json_str) => {
-                if let Err(e) = ws.send_with_str(&json_str) {
-                    log(&format!("Send error: {:?}", e));
+            Ok(json_str) => match ws.send(&json_str) {
+                Ok(()) => true,
+                Err(e) => {
+                    log(&format!("Send error: {}", e));
+                    false
                 }
-            }
+            },
             Err(e) => {
                 log(&format!("Serialization error: {:?}", e));
+                false
             }
         }
     }
 
-    pub fn send_encrypted_message(ws: &WebSocket, message: &ClientMessage, crypto: &Crypto) {
-        if crypto.aes_cipher.borrow().is_none()
-            && !matches!(message, ClientMessage::PublicKey { .. })
-        {
-            let error_msg = format!(
-                "tried to send msg of type {:?} before session key has been got",
-                message
+    // held in the socket's outbox until the session is ready and the socket is open; only
+    // the resume request and heartbeats go out earlier. returns the request id the server
+    // echoes when it answers an action
+    pub fn send_encrypted_message(
+        ws: &Socket,
+        message: &ClientMessage,
+        crypto: &Crypto,
+    ) -> Option<u64> {
+        let has_session = crypto.aes_cipher.borrow().is_some();
+        if !has_session && matches!(message, ClientMessage::PublicKey { .. }) {
+            Self::send_message(ws, message);
+            return None;
+        }
+
+        let request_id = message
+            .request_id()
+            .or_else(|| message.is_action().then(|| ws.requests.next_id()));
+        let mut value = match serde_json::to_value(message) {
+            Ok(value) => value,
+            Err(e) => {
+                log(&format!("Serialization error: {:?}", e));
+                return None;
+            }
+        };
+        if let (Some(request_id), Some(fields)) = (request_id, value.as_object_mut()) {
+            fields.insert("request_id".to_string(), request_id.into());
+        }
+
+        let held = !ws.is_ready()
+            && !matches!(
+                message,
+                ClientMessage::Resume { .. } | ClientMessage::Ping { .. }
             );
-            log(&error_msg);
-            return;
+        if !has_session || !ws.is_open() || held {
+            let pushed = ws.outbox.borrow_mut().push(message, value);
+            // whoever waits on a message that will never be sent hears about it now
+            let displaced = pushed.as_ref().map_or(&[][..], |ids| ids.as_slice());
+            for displaced_id in displaced {
+                ws.requests
+                    .reject(*displaced_id, "Request dropped from the outbox");
+            }
+            return pushed.and(request_id);
         }
 
-        match serde_json::to_string(message) {
-            Ok(json_str) => {
-                if let Some((data, nonce)) = crypto.encrypt(&json_str) {
-                    let encrypted_msg = ClientMessage::EncryptedMessage { data, nonce };
-                    Self::send_message(ws, &encrypted_msg);
-                } else {
-                    if matches!(message, ClientMessage::PublicKey { .. }) {
-                        Self::send_message(ws, message);
-                    }
+        if Self::send_encrypted(ws, &value, crypto) {
+            request_id
+        } else {
+            None
+        }
+    }
+
+    pub fn flush_outbox(ws: &Socket, crypto: &Crypto) {
+        let queued = ws.outbox.borrow_mut().drain();
+        for message in queued {
+            if !Self::send_encrypted(ws, &message, crypto)
+                && let Some(request_id) = Outbox::request_id(&message)
+            {
+                ws.requests.reject(request_id, "Request could not be sent");
+            }
+        }
+    }
+
+    // binary encodings travel as raw nonce + ciphertext frames instead of base64 in JSON
+    fn send_encrypted(ws: &Socket, message: &serde_json::Value, crypto: &Crypto) -> bool {
+        let compressible = message
+            .get("action")
+            .and_then(|action| action.as_str())
+            .is_none_or(Compression::compressible);
+        let encoding = ws.encoding();
+        if !encoding.is_binary() {
+            return Self::send_encrypted_json(ws, &message.to_string(), compressible, crypto);
+        }
+
+        let compression = ws.compression();
+        match encoding
+            .encode(message)
+            .map(|bytes| crypto.encrypt_frame(&compression.pack(bytes, compressible)))
+        {
+            Ok(Some(frame)) => match ws.send_bytes(&frame) {
+                Ok(()) => true,
+                Err(e) => {
+                    log(&format!("Send error: {}", e));
+                    false
                 }
+            },
+            Ok(None) => {
+                log("Failed to encrypt outgoing message");
+                false
+            }
+            Err(e) => {
+                log(&e.to_string());
+                false
+            }
+        }
+    }
+
+    // small payloads skip compression, so the envelope says whether this one was deflated
+    fn send_encrypted_json(
+        ws: &Socket,
+        json_str: &str,
+        compressible: bool,
+        crypto: &Crypto,
+    ) -> bool {
+        let deflated = if compressible {
+            ws.compression().compress(json_str.as_bytes())
+        } else {
+            None
+        };
+        let (encrypted, compressed) = match deflated {
+            Some(deflated) => (crypto.encrypt_bytes(&deflated), Some(true)),
+            None => (crypto.encrypt(json_str), None),
+        };
+        match encrypted {
+            Some((data, nonce)) => Self::send_message(
+                ws,
+                &ClientMessage::EncryptedMessage {
+                    data,
+                    nonce,
+                    compressed,
+                },
+            ),
+            None => {
+                log("Failed to encrypt outgoing message");
+                false
             }
-            Err(e) => log(&format!("Serialization error: {:?}", e)),
         }
     }
 }
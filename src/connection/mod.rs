@@ -1,13 +1,39 @@
+pub mod codec;
 pub mod core;
 pub mod crypto;
+pub mod dispatcher;
 pub mod event_handler;
 pub mod handler;
+pub mod heartbeat;
 pub mod messages;
 pub mod messaging;
+pub mod outbox;
+pub mod protocol;
+pub mod reconnect;
+pub mod requests;
+pub mod session;
+pub mod socket;
+pub mod state;
+pub mod subscriptions;
+pub mod transport;
+pub mod upload;
 
+pub use codec::*;
 pub use core::*;
 pub use crypto::*;
+pub use dispatcher::*;
 pub use event_handler::*;
 pub use handler::*;
+pub use heartbeat::*;
 pub use messages::*;
 pub use messaging::*;
+pub use outbox::*;
+pub use protocol::*;
+pub use reconnect::*;
+pub use requests::*;
+pub use session::*;
+pub use socket::*;
+pub use state::*;
+pub use subscriptions::*;
+pub use transport::*;
+pub use upload::*;
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use crate::connection::ClientMessage;
+use crate::utils::log;
+
+#[derive(Debug, PartialEq)]
+pub enum OutboxPolicy {
+    // stale once the connection is back, or resent by the handshake itself
+    Drop,
+    // only the latest message with the same key is worth delivering
+    Coalesce(String),
+    Queue,
+}
+
+// messages that could not be sent yet, kept as values so borrowed messages can wait and
+// are encoded with whatever the next session negotiates
+#[derive(Debug, Default)]
+pub struct Outbox {
+    entries: VecDeque<(Option<String>, serde_json::Value)>,
+}
+
+impl Outbox {
+    const CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn policy(message: &ClientMessage) -> OutboxPolicy {
+        match message {
+            // the handshake resends its own
+            ClientMessage::Resume { .. }
+            | ClientMessage::PublicKey { .. }
+            | ClientMessage::EncryptedMessage { .. } => OutboxPolicy::Drop,
+            // a late ping would only measure the outage
+            ClientMessage::Ping { .. } => OutboxPolicy::Drop,
+            // chunks are bound to the session that accepted the upload
+            ClientMessage::UploadChunk { .. } | ClientMessage::UploadComplete { .. } => {
+                OutboxPolicy::Drop
+            }
+            ClientMessage::Preferences { .. } => OutboxPolicy::Coalesce("preferences".into()),
+            // a link click leaves history alone until the page renders, so the handshake
+            // would only navigate back to the page the click was leaving; the last click wins
+            ClientMessage::Navigate { .. } => OutboxPolicy::Coalesce("navigate".into()),
+            // subscription events carry no request and only the latest state matters
+            ClientMessage::EventCallback {
+                id,
+                event_type,
+                request_id: None,
+                ..
+            } => OutboxPolicy::Coalesce(format!("event:{}:{}", id, event_type)),
+            ClientMessage::Callback { .. }
+            | ClientMessage::EventCallback { .. }
+            | ClientMessage::Push { .. }
+            | ClientMessage::ClientError { .. }
+            | ClientMessage::UploadStart { .. } => OutboxPolicy::Queue,
+        }
+    }
+
+    // the value is the message as it will be sent, request id included. returns None when
+    // the message itself is dropped, otherwise the request ids of the messages it displaced
+    pub fn push(&mut self, message: &ClientMessage, value: serde_json::Value) -> Option<Vec<u64>> {
+        let mut displaced = Vec::new();
+        let key = match Self::policy(message) {
+            OutboxPolicy::Drop => return None,
+            OutboxPolicy::Coalesce(key) => {
+                self.entries.retain(|(existing, value)| {
+                    let replaced = existing.as_deref() == Some(key.as_str());
+                    if replaced {
+                        displaced.extend(Self::request_id(value));
+                    }
+                    !replaced
+                });
+                Some(key)
+            }
+            OutboxPolicy::Queue => None,
+        };
+
+        // payloads carry form data and file names, so only the action is logged
+        if self.entries.len() >= Self::CAPACITY
+            && let Some((dropped_key, dropped)) = self.entries.pop_front()
+        {
+            displaced.extend(Self::request_id(&dropped));
+            log(&format!(
+                "Outbox full, dropping oldest {} message{}",
+                dropped
+                    .get("action")
+                    .and_then(|action| action.as_str())
+                    .unwrap_or("unknown"),
+                dropped_key
+                    .map(|key| format!(" ({})", key))
+                    .unwrap_or_default()
+            ));
+        }
+        self.entries.push_back((key, value));
+        Some(displaced)
+    }
+
+    pub fn request_ids(&self) -> Vec<u64> {
+        self.entries
+            .iter()
+            .filter_map(|(_, value)| Self::request_id(value))
+            .collect()
+    }
+
+    pub fn request_id(value: &serde_json::Value) -> Option<u64> {
+        value.get("request_id").and_then(|id| id.as_u64())
+    }
+
+    pub fn drain(&mut self) -> Vec<serde_json::Value> {
+        self.entries.drain(..).map(|(_, value)| value).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(request_id: Option<u64>) -> ClientMessage<'static> {
+        ClientMessage::EventCallback {
+            id: "cb",
+            event_type: "scroll".to_string(),
+            event_data: String::new(),
+            validity: None,
+            request_id,
+        }
+    }
+
+    #[test]
+    fn drops_session_bound_messages() {
+        assert_eq!(
+            Outbox::policy(&ClientMessage::Ping {
+                sent_at: 0.0,
+                latency: None
+            }),
+            OutboxPolicy::Drop
+        );
+    }
+
+    #[test]
+    fn coalesces_subscription_events_but_queues_requests() {
+        assert_eq!(
+            Outbox::policy(&event(None)),
+            OutboxPolicy::Coalesce("event:cb:scroll".to_string())
+        );
+        assert_eq!(Outbox::policy(&event(Some(1))), OutboxPolicy::Queue);
+    }
+
+    #[test]
+    fn keeps_the_last_link_clicked() {
+        let mut outbox = Outbox::new();
+        outbox.push(
+            &ClientMessage::Navigate { path: "/a" },
+            serde_json::json!({ "path": "/a" }),
+        );
+        outbox.push(
+            &ClientMessage::Navigate { path: "/b" },
+            serde_json::json!({ "path": "/b" }),
+        );
+        assert_eq!(outbox.drain(), vec![serde_json::json!({ "path": "/b" })]);
+    }
+
+    #[test]
+    fn reports_what_was_dropped_or_displaced() {
+        let mut outbox = Outbox::new();
+        assert_eq!(
+            outbox.push(
+                &ClientMessage::Ping {
+                    sent_at: 0.0,
+                    latency: None
+                },
+                serde_json::json!({})
+            ),
+            None
+        );
+        assert_eq!(
+            outbox.push(&event(None), serde_json::json!({ "request_id": 4 })),
+            Some(vec![])
+        );
+        assert_eq!(
+            outbox.push(&event(None), serde_json::json!({ "request_id": 5 })),
+            Some(vec![4])
+        );
+        assert_eq!(outbox.request_ids(), vec![5]);
+    }
+
+    #[test]
+    fn keeps_only_the_latest_coalesced_message() {
+        let mut outbox = Outbox::new();
+        outbox.push(&event(None), serde_json::json!({ "n": 1 }));
+        outbox.push(
+            &ClientMessage::Callback {
+                id: "a",
+                request_id: Some(1),
+            },
+            serde_json::json!({ "n": 2 }),
+        );
+        outbox.push(&event(None), serde_json::json!({ "n": 3 }));
+
+        assert_eq!(
+            outbox.drain(),
+            vec![serde_json::json!({ "n": 2 }), serde_json::json!({ "n": 3 })]
+        );
+    }
+}
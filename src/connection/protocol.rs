@@ -0,0 +1,106 @@
+use web_sys::{Storage, Window};
+
+use crate::error::AppError;
+use crate::utils::{EventDispatcher, log};
+
+// version of the messages exchanged after the handshake; bumped whenever a change
+// would be misread by a bundle built against the previous one
+pub struct Protocol;
+
+impl Protocol {
+    pub const VERSION: u32 = 1;
+    pub const FEATURES: &'static [&'static str] = &[
+        "resume",
+        "heartbeat",
+        "request_ids",
+        "push",
+        "subscriptions",
+        "uploads",
+    ];
+    pub const MISMATCH_EVENT: &'static str = "quillion:protocol-mismatch";
+    // the server version a reload was last attempted for, so a stale cache cannot loop
+    const RELOAD_KEY: &'static str = "quillion-protocol-reload";
+
+    // servers that predate negotiation send no version and are taken as compatible
+    pub fn check(server: Option<u32>) -> Result<(), AppError> {
+        match server {
+            Some(server) if server != Self::VERSION => Err(AppError::ProtocolMismatch {
+                client: Self::VERSION,
+                server,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // a newer server means this bundle is stale, which one reload can fix; anything else
+    // cannot be fixed from here. returns true if the page is reloading
+    pub fn handle_mismatch(window: &Window, server: u32) -> bool {
+        let storage = Self::storage(window);
+        let already_tried = storage
+            .as_ref()
+            .and_then(|s| s.get_item(Self::RELOAD_KEY).ok().flatten())
+            .is_some_and(|tried| tried == server.to_string());
+        let reload = server > Self::VERSION && !already_tried;
+
+        log(&AppError::ProtocolMismatch {
+            client: Self::VERSION,
+            server,
+        }
+        .to_string());
+        if let Some(root) = window.document().and_then(|d| d.document_element()) {
+            EventDispatcher::dispatch(
+                &root,
+                Self::MISMATCH_EVENT,
+                &serde_json::json!({
+                    "client": Self::VERSION,
+                    "server": server,
+                    "reload": reload,
+                }),
+            );
+        }
+
+        if reload {
+            if let Some(storage) = &storage {
+                let _ = storage.set_item(Self::RELOAD_KEY, &server.to_string());
+            }
+            let _ = window.location().reload();
+        }
+        reload
+    }
+
+    pub fn agreed(window: &Window) {
+        if let Some(storage) = Self::storage(window) {
+            let _ = storage.remove_item(Self::RELOAD_KEY);
+        }
+    }
+
+    fn storage(window: &Window) -> Option<Storage> {
+        window.session_storage().ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_same_version() {
+        assert!(Protocol::check(Some(Protocol::VERSION)).is_ok());
+    }
+
+    #[test]
+    fn accepts_servers_that_predate_negotiation() {
+        assert!(Protocol::check(None).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        for server in [Protocol::VERSION + 1, Protocol::VERSION - 1] {
+            assert!(matches!(
+                Protocol::check(Some(server)),
+                Err(AppError::ProtocolMismatch { client, server: reported })
+                    if client == Protocol::VERSION && reported == server
+            ));
+        }
+    }
+}
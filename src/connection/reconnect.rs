@@ -0,0 +1,188 @@
+use rand::RngCore;
+use rand::rngs::OsRng;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::Window;
+
+use crate::utils::{MetaConfig, log};
+
+pub struct Reconnector {
+    base_delay_ms: u32,
+    max_delay_ms: u32,
+    max_retries: u32,
+    attempts: Cell<u32>,
+    timer: Cell<Option<i32>>,
+    gave_up: Cell<bool>,
+    halted: Cell<bool>,
+}
+
+impl Reconnector {
+    pub fn new(config: &MetaConfig) -> Self {
+        Self {
+            base_delay_ms: config.reconnect_delay_ms.max(1),
+            max_delay_ms: config.reconnect_max_delay_ms.max(config.reconnect_delay_ms),
+            max_retries: config.reconnect_max_retries,
+            attempts: Cell::new(0),
+            timer: Cell::new(None),
+            gave_up: Cell::new(false),
+            halted: Cell::new(false),
+        }
+    }
+
+    // called once a socket opens; the next outage starts from the base delay again
+    pub fn reset(&self) {
+        self.attempts.set(0);
+        self.gave_up.set(false);
+    }
+
+    // for failures no retry can fix; unlike giving up, coming back online does not undo it
+    pub fn halt(&self, window: &Window) {
+        self.halted.set(true);
+        if let Some(handle) = self.timer.take() {
+            window.clear_timeout_with_handle(handle);
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted.get()
+    }
+
+    // returns false once the retry budget is spent
+    pub fn schedule(self: &Rc<Self>, window: &Window, reconnect: impl FnOnce() + 'static) -> bool {
+        if self.halted.get() {
+            return false;
+        }
+        let Some(attempt) = self.next_attempt() else {
+            if !self.gave_up.replace(true) {
+                log(&format!(
+                    "Giving up after {} reconnect attempts",
+                    self.max_retries
+                ));
+            }
+            return false;
+        };
+
+        let delay = self.delay(attempt);
+        log(&format!(
+            "Reconnecting in {} ms (attempt {}/{})",
+            delay,
+            attempt + 1,
+            self.max_retries
+        ));
+
+        let this = self.clone();
+        let callback = Closure::once_into_js(move || {
+            this.timer.set(None);
+            reconnect();
+        });
+        match window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(callback.unchecked_ref(), delay)
+        {
+            Ok(handle) => self.timer.set(Some(handle)),
+            Err(e) => log(&format!("Failed to schedule reconnect: {:?}", e)),
+        }
+        true
+    }
+
+    // skips the remaining wait when the browser reports the network is back
+    pub fn retry_now(&self, window: &Window, reconnect: impl FnOnce()) -> bool {
+        if self.halted.get() {
+            return false;
+        }
+        let waiting = match self.timer.take() {
+            Some(handle) => {
+                window.clear_timeout_with_handle(handle);
+                true
+            }
+            None => self.gave_up.get(),
+        };
+        if waiting {
+            self.reset();
+            reconnect();
+        }
+        waiting
+    }
+
+    // None once the retry budget is spent
+    fn next_attempt(&self) -> Option<u32> {
+        let attempt = self.attempts.get();
+        if attempt >= self.max_retries {
+            return None;
+        }
+        self.attempts.set(attempt + 1);
+        Some(attempt)
+    }
+
+    // exponential growth capped at the maximum, with the upper half randomised
+    // so clients dropped by the same restart do not return in lockstep
+    fn delay(&self, attempt: u32) -> i32 {
+        let ceiling = (self.base_delay_ms as u64)
+            .saturating_mul(1u64 << attempt.min(31))
+            .min(self.max_delay_ms as u64);
+        let half = ceiling / 2;
+        let jitter = OsRng.next_u64() % (ceiling - half + 1);
+        (half + jitter).min(i32::MAX as u64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnector(base: u32, max: u32, retries: u32) -> Reconnector {
+        Reconnector::new(&MetaConfig {
+            reconnect_delay_ms: base,
+            reconnect_max_delay_ms: max,
+            reconnect_max_retries: retries,
+            ..MetaConfig::default()
+        })
+    }
+
+    // the jittered delay always lands in the upper half of the ceiling
+    fn assert_within(reconnector: &Reconnector, attempt: u32, ceiling: i32) {
+        for _ in 0..64 {
+            let delay = reconnector.delay(attempt);
+            assert!(
+                (ceiling / 2..=ceiling).contains(&delay),
+                "attempt {attempt}: {delay} outside {}..={ceiling}",
+                ceiling / 2
+            );
+        }
+    }
+
+    #[test]
+    fn doubles_the_delay_per_attempt() {
+        let reconnector = reconnector(500, 30_000, 12);
+        assert_within(&reconnector, 0, 500);
+        assert_within(&reconnector, 1, 1_000);
+        assert_within(&reconnector, 3, 4_000);
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        let reconnector = reconnector(500, 30_000, 12);
+        assert_within(&reconnector, 7, 30_000);
+        assert_within(&reconnector, 40, 30_000);
+    }
+
+    #[test]
+    fn never_caps_below_the_base_delay() {
+        let reconnector = reconnector(2_000, 100, 12);
+        assert_within(&reconnector, 0, 2_000);
+        assert_within(&reconnector, 5, 2_000);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let reconnector = reconnector(500, 30_000, 3);
+        assert_eq!(reconnector.next_attempt(), Some(0));
+        assert_eq!(reconnector.next_attempt(), Some(1));
+        assert_eq!(reconnector.next_attempt(), Some(2));
+        assert_eq!(reconnector.next_attempt(), None);
+
+        reconnector.reset();
+        assert_eq!(reconnector.next_attempt(), Some(0));
+    }
+}
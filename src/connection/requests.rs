@@ -0,0 +1,88 @@
+use js_sys::{Function, Promise};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::Window;
+
+struct Waiter {
+    resolve: Function,
+    reject: Function,
+}
+
+// hands out the id every outgoing action carries, and holds the promises of callers
+// waiting for the server to answer one
+#[derive(Default)]
+pub struct Requests {
+    next_id: Cell<u64>,
+    waiting: RefCell<HashMap<u64, Waiter>>,
+}
+
+impl Requests {
+    // a reply lost along with its connection must not leave the caller waiting forever
+    const TIMEOUT_MS: i32 = 30_000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn next_id(&self) -> u64 {
+        let request_id = self.next_id.get() + 1;
+        self.next_id.set(request_id);
+        request_id
+    }
+
+    pub fn wait(self: &Rc<Self>, window: &Window, request_id: u64) -> Promise {
+        let mut waiter = None;
+        let promise = Promise::new(&mut |resolve, reject| {
+            waiter = Some(Waiter { resolve, reject });
+        });
+        if let Some(waiter) = waiter {
+            self.waiting.borrow_mut().insert(request_id, waiter);
+        }
+
+        let requests = self.clone();
+        let expire =
+            Closure::once_into_js(move || requests.reject(request_id, "Request timed out"));
+        let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            expire.unchecked_ref(),
+            Self::TIMEOUT_MS,
+        );
+        promise
+    }
+
+    pub fn resolve(&self, request_id: u64, data: Option<&serde_json::Value>) {
+        let waiter = self.waiting.borrow_mut().remove(&request_id);
+        if let Some(waiter) = waiter {
+            let data = data
+                .and_then(|data| js_sys::JSON::parse(&data.to_string()).ok())
+                .unwrap_or(JsValue::UNDEFINED);
+            let _ = waiter.resolve.call1(&JsValue::NULL, &data);
+        }
+    }
+
+    // replies to anything sent on a closed socket are lost with it; requests still in the
+    // outbox go out on the next one
+    pub fn reject_except(&self, keep: &[u64], error: &str) {
+        let lost: Vec<u64> = self
+            .waiting
+            .borrow()
+            .keys()
+            .filter(|request_id| !keep.contains(request_id))
+            .copied()
+            .collect();
+        for request_id in lost {
+            self.reject(request_id, error);
+        }
+    }
+
+    pub fn reject(&self, request_id: u64, error: &str) {
+        let waiter = self.waiting.borrow_mut().remove(&request_id);
+        if let Some(waiter) = waiter {
+            let _ = waiter
+                .reject
+                .call1(&JsValue::NULL, &js_sys::Error::new(error));
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::cell::RefCell;
+use web_sys::{Storage, Window};
+
+// opaque resumption token issued by the server; kept in memory and mirrored to
+// sessionStorage so it also survives a reload of the same tab
+#[derive(Debug)]
+pub struct Session {
+    storage: Option<Storage>,
+    token: RefCell<Option<String>>,
+}
+
+impl Session {
+    const STORAGE_KEY: &'static str = "quillion-session";
+
+    pub fn new(window: &Window) -> Self {
+        let storage = window.session_storage().ok().flatten();
+        let token = storage
+            .as_ref()
+            .and_then(|s| s.get_item(Self::STORAGE_KEY).ok().flatten());
+
+        Self {
+            storage,
+            token: RefCell::new(token),
+        }
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.borrow().clone()
+    }
+
+    pub fn set(&self, token: &str) {
+        if let Some(storage) = &self.storage {
+            let _ = storage.set_item(Self::STORAGE_KEY, token);
+        }
+        *self.token.borrow_mut() = Some(token.to_string());
+    }
+
+    pub fn clear(&self) {
+        if let Some(storage) = &self.storage {
+            let _ = storage.remove_item(Self::STORAGE_KEY);
+        }
+        *self.token.borrow_mut() = None;
+    }
+}
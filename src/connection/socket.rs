@@ -0,0 +1,100 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::connection::Outbox;
+use crate::connection::Requests;
+use crate::connection::Transport;
+use crate::connection::{Compression, Encoding};
+use crate::error::AppError;
+
+// shared handle to the live transport; a reconnect swaps the inner transport so every
+// closure holding a clone sends through the new one
+#[derive(Clone)]
+pub struct Socket {
+    inner: Rc<RefCell<Rc<dyn Transport>>>,
+    encoding: Rc<Cell<Encoding>>,
+    compression: Rc<Cell<Compression>>,
+    features: Rc<RefCell<Vec<String>>>,
+    ready: Rc<Cell<bool>>,
+    pub outbox: Rc<RefCell<Outbox>>,
+    pub requests: Rc<Requests>,
+}
+
+impl Socket {
+    pub fn new(transport: Rc<dyn Transport>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(transport)),
+            encoding: Rc::new(Cell::new(Encoding::default())),
+            compression: Rc::new(Cell::new(Compression::default())),
+            features: Rc::new(RefCell::new(Vec::new())),
+            ready: Rc::new(Cell::new(false)),
+            outbox: Rc::new(RefCell::new(Outbox::new())),
+            requests: Rc::new(Requests::new()),
+        }
+    }
+
+    pub fn current(&self) -> Rc<dyn Transport> {
+        self.inner.borrow().clone()
+    }
+
+    // a new transport starts a new handshake, which negotiates its own encoding, compression
+    // and features
+    pub fn replace(&self, transport: Rc<dyn Transport>) {
+        *self.inner.borrow_mut() = transport;
+        self.encoding.set(Encoding::default());
+        self.compression.set(Compression::default());
+        self.features.borrow_mut().clear();
+        self.ready.set(false);
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding.get()
+    }
+
+    pub fn set_encoding(&self, encoding: Encoding) {
+        self.encoding.set(encoding);
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression.get()
+    }
+
+    pub fn set_compression(&self, compression: Compression) {
+        self.compression.set(compression);
+    }
+
+    // only what the server listed in the handshake
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.borrow().iter().any(|f| f == feature)
+    }
+
+    pub fn set_features(&self, features: Vec<String>) {
+        *self.features.borrow_mut() = features;
+    }
+
+    // set once the server has resumed or started the session; until then actions are queued
+    // so they cannot overtake earlier ones or land in a session about to be reset
+    pub fn is_ready(&self) -> bool {
+        self.ready.get()
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.set(ready);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.inner.borrow().is_open()
+    }
+
+    pub fn buffered_amount(&self) -> u32 {
+        self.inner.borrow().buffered_amount()
+    }
+
+    pub fn send(&self, data: &str) -> Result<(), AppError> {
+        self.inner.borrow().send(data)
+    }
+
+    pub fn send_bytes(&self, data: &[u8]) -> Result<(), AppError> {
+        self.inner.borrow().send_bytes(data)
+    }
+}
@@ -0,0 +1,85 @@
+use std::cell::Cell;
+use web_sys::{Element, Window};
+
+use crate::utils::EventDispatcher;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Handshaking,
+    Connected,
+    Reconnecting,
+    Offline,
+    Failed,
+    // the server speaks a protocol version this bundle cannot
+    Incompatible,
+}
+
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Handshaking => "handshaking",
+            Self::Connected => "connected",
+            Self::Reconnecting => "reconnecting",
+            Self::Offline => "offline",
+            Self::Failed => "failed",
+            Self::Incompatible => "incompatible",
+        }
+    }
+
+    fn class_name(&self) -> String {
+        format!("{}{}", ConnectionStatus::CLASS_PREFIX, self.as_str())
+    }
+}
+
+// mirrors the current state onto <html> so pages can style it without scripts
+pub struct ConnectionStatus {
+    root: Option<Element>,
+    current: Cell<ConnectionState>,
+}
+
+impl ConnectionStatus {
+    pub const ATTR: &'static str = "data-quillion-state";
+    pub const CLASS_PREFIX: &'static str = "quillion-";
+    pub const CHANGE_EVENT: &'static str = "quillion:connection-state";
+
+    pub fn new(window: &Window) -> Self {
+        let status = Self {
+            root: window.document().and_then(|d| d.document_element()),
+            current: Cell::new(ConnectionState::Connecting),
+        };
+        if let Some(root) = &status.root {
+            let _ = root.set_attribute(Self::ATTR, ConnectionState::Connecting.as_str());
+            let _ = root
+                .class_list()
+                .add_1(&ConnectionState::Connecting.class_name());
+        }
+        status
+    }
+
+    pub fn get(&self) -> ConnectionState {
+        self.current.get()
+    }
+
+    pub fn set(&self, state: ConnectionState) {
+        let previous = self.current.replace(state);
+        if previous == state {
+            return;
+        }
+
+        let Some(root) = &self.root else {
+            return;
+        };
+        let _ = root.set_attribute(Self::ATTR, state.as_str());
+        let class_list = root.class_list();
+        let _ = class_list.remove_1(&previous.class_name());
+        let _ = class_list.add_1(&state.class_name());
+
+        EventDispatcher::dispatch(
+            root,
+            Self::CHANGE_EVENT,
+            &serde_json::json!({ "state": state.as_str(), "previous": previous.as_str() }),
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{Event, EventTarget, Window};
+
+use crate::connection::{ClientMessage, Crypto, Messaging, Socket};
+use crate::error::AppError;
+use crate::utils::EventDataExtractor;
+
+#[derive(Default)]
+struct ThrottleState {
+    last_sent: f64,
+    pending: Option<String>,
+}
+
+struct Subscription {
+    target: EventTarget,
+    event_type: String,
+    closure: Closure<dyn FnMut(Event)>,
+    state: Rc<RefCell<ThrottleState>>,
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    active: HashMap<(String, String), Subscription>,
+    // path of the last rendered page; on back/forward the location moves before the render
+    // arrives, so it cannot be compared against the location
+    page: Option<String>,
+}
+
+impl Subscriptions {
+    const CONTINUOUS_EVENTS: [&'static str; 3] = ["resize", "scroll", "mousemove"];
+    const CONTINUOUS_THROTTLE_MS: u32 = 100;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn subscribe(
+        &mut self,
+        ws: &Socket,
+        window: &Window,
+        crypto: &Rc<RefCell<Crypto>>,
+        target: &str,
+        event_type: &str,
+        callback_id: &str,
+        throttle_ms: Option<u32>,
+    ) -> Result<(), AppError> {
+        let event_target: EventTarget = match target {
+            "window" => window.clone().into(),
+            "document" => window.document().ok_or(AppError::DocumentNotFound)?.into(),
+            other => {
+                return Err(AppError::InvalidState(format!(
+                    "Unknown subscription target: {}",
+                    other
+                )));
+            }
+        };
+
+        self.unsubscribe(callback_id, Some(event_type));
+
+        let throttle_ms = throttle_ms.unwrap_or_else(|| {
+            if Self::CONTINUOUS_EVENTS.contains(&event_type) {
+                Self::CONTINUOUS_THROTTLE_MS
+            } else {
+                0
+            }
+        });
+
+        let ws_clone = ws.clone();
+        let window_clone = window.clone();
+        let crypto_clone = crypto.clone();
+        let event_type_clone = event_type.to_string();
+        let callback_id_clone = callback_id.to_string();
+        let state = Rc::new(RefCell::new(ThrottleState::default()));
+        let closure_state = state.clone();
+
+        let closure = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let event_data =
+                EventDataExtractor::extract_global_data(&window_clone, &event, &event_type_clone)
+                    .map(|data| data.to_string())
+                    .unwrap_or_default();
+
+            let send = {
+                let ws = ws_clone.clone();
+                let crypto = crypto_clone.clone();
+                let id = callback_id_clone.clone();
+                let event_type = event_type_clone.clone();
+                move |event_data: String| {
+                    Messaging::send_encrypted_message(
+                        &ws,
+                        &ClientMessage::EventCallback {
+                            id: &id,
+                            event_type: event_type.clone(),
+                            event_data,
+                            validity: None,
+                            request_id: None,
+                        },
+                        &crypto.borrow(),
+                    );
+                }
+            };
+
+            let now = js_sys::Date::now();
+            let mut throttle = closure_state.borrow_mut();
+            let elapsed = now - throttle.last_sent;
+
+            if elapsed >= throttle_ms as f64 {
+                throttle.last_sent = now;
+                drop(throttle);
+                send(event_data);
+                return;
+            }
+
+            // keep only the latest payload and flush it once the window closes
+            let already_scheduled = throttle.pending.replace(event_data).is_some();
+            drop(throttle);
+            if already_scheduled {
+                return;
+            }
+
+            let state = closure_state.clone();
+            let flush = Closure::once_into_js(move || {
+                let mut throttle = state.borrow_mut();
+                throttle.last_sent = js_sys::Date::now();
+                let pending = throttle.pending.take();
+                drop(throttle);
+                if let Some(event_data) = pending {
+                    send(event_data);
+                }
+            });
+            let _ = window_clone.set_timeout_with_callback_and_timeout_and_arguments_0(
+                flush.unchecked_ref(),
+                (throttle_ms as f64 - elapsed).ceil() as i32,
+            );
+        });
+
+        event_target
+            .add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())?;
+
+        self.active.insert(
+            (callback_id.to_string(), event_type.to_string()),
+            Subscription {
+                target: event_target,
+                event_type: event_type.to_string(),
+                closure,
+                state,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, callback_id: &str, event_type: Option<&str>) {
+        let keys: Vec<_> = self
+            .active
+            .keys()
+            .filter(|(id, event)| id == callback_id && event_type.is_none_or(|e| e == event))
+            .cloned()
+            .collect();
+
+        for key in keys {
+            if let Some(subscription) = self.active.remove(&key) {
+                Self::detach(subscription);
+            }
+        }
+    }
+
+    // subscriptions belong to the page that made them and end when another one renders
+    pub fn enter_page(&mut self, path: &str) {
+        if self.page.as_deref().is_some_and(|page| page != path) {
+            self.clear();
+        }
+        self.page = Some(path.to_string());
+    }
+
+    pub fn clear(&mut self) {
+        for (_, subscription) in self.active.drain() {
+            Self::detach(subscription);
+        }
+    }
+
+    fn detach(subscription: Subscription) {
+        subscription.state.borrow_mut().pending = None;
+        let _ = subscription.target.remove_event_listener_with_callback(
+            &subscription.event_type,
+            subscription.closure.as_ref().unchecked_ref(),
+        );
+    }
+}
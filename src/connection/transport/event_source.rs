@@ -0,0 +1,181 @@
+use gloo_net::http::Request;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{Event, EventSource, MessageEvent};
+
+use super::{Frame, Transport, TransportKind};
+use crate::error::AppError;
+use crate::utils::log;
+
+// server frames arrive over an EventSource stream, client frames go out as ordered POSTs;
+// both carry the same connection id so the server can pair them up
+pub struct EventSourceTransport {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    source: EventSource,
+    send_url: String,
+    queue: RefCell<VecDeque<String>>,
+    sending: Cell<bool>,
+    closed: Cell<bool>,
+    on_close: RefCell<Option<Box<dyn FnMut()>>>,
+}
+
+impl EventSourceTransport {
+    pub fn open(base_url: &str) -> Result<Self, AppError> {
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let connection_id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let base_url = base_url.trim_end_matches('/');
+
+        let source = EventSource::new(&format!("{}/events?cid={}", base_url, connection_id))
+            .map_err(|e| AppError::TransportError(format!("{:?}", e)))?;
+
+        Ok(Self {
+            inner: Rc::new(Inner {
+                source,
+                send_url: format!("{}/send?cid={}", base_url, connection_id),
+                queue: RefCell::new(VecDeque::new()),
+                sending: Cell::new(false),
+                closed: Cell::new(false),
+                on_close: RefCell::new(None),
+            }),
+        })
+    }
+}
+
+impl Inner {
+    // EventSource has no close event of its own, so every way of ending the stream goes here
+    fn shut(&self) {
+        if self.closed.replace(true) {
+            return;
+        }
+        self.source.close();
+        self.queue.borrow_mut().clear();
+        if let Some(callback) = self.on_close.borrow_mut().as_mut() {
+            callback();
+        }
+    }
+
+    // one request in flight at a time keeps client frames in order
+    fn pump(self: &Rc<Self>) {
+        if self.sending.replace(true) {
+            return;
+        }
+
+        let inner = self.clone();
+        spawn_local(async move {
+            loop {
+                let next = inner.queue.borrow_mut().pop_front();
+                let Some(body) = next else {
+                    break;
+                };
+
+                let sent = match Request::post(&inner.send_url)
+                    .header("Content-Type", "text/plain;charset=UTF-8")
+                    .body(body)
+                {
+                    Ok(request) => request.send().await.map(|response| response.ok()),
+                    Err(e) => Err(e),
+                };
+
+                if !matches!(sent, Ok(true)) {
+                    log(&format!("Fallback send failed: {:?}", sent));
+                    inner.sending.set(false);
+                    inner.shut();
+                    return;
+                }
+            }
+            inner.sending.set(false);
+        });
+    }
+}
+
+impl Transport for EventSourceTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::EventSource
+    }
+
+    // event streams are text only, so binary encodings are never offered over them
+    fn supports_binary(&self) -> bool {
+        false
+    }
+
+    fn send_bytes(&self, _data: &[u8]) -> Result<(), AppError> {
+        Err(AppError::TransportError(
+            "Event stream transport cannot send binary frames".to_string(),
+        ))
+    }
+
+    fn send(&self, data: &str) -> Result<(), AppError> {
+        if !self.is_open() {
+            return Err(AppError::TransportError(
+                "Event stream is not open".to_string(),
+            ));
+        }
+        self.inner.queue.borrow_mut().push_back(data.to_string());
+        self.inner.pump();
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        !self.inner.closed.get() && self.inner.source.ready_state() == EventSource::OPEN
+    }
+
+    fn buffered_amount(&self) -> u32 {
+        self.inner
+            .queue
+            .borrow()
+            .iter()
+            .map(|body| body.len() as u32)
+            .sum()
+    }
+
+    fn close(&self) {
+        self.inner.shut();
+    }
+
+    fn on_open(&self, callback: Box<dyn FnMut()>) {
+        let onopen_callback = Closure::wrap(callback);
+        self.inner
+            .source
+            .set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+        onopen_callback.forget();
+    }
+
+    fn on_message(&self, mut callback: Box<dyn FnMut(Frame)>) {
+        let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
+            if let Some(data) = e.data().as_string() {
+                callback(Frame::Text(data));
+            }
+        });
+        self.inner
+            .source
+            .set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+        onmessage_callback.forget();
+    }
+
+    // the browser would silently retry the stream; reconnects are ours to schedule
+    fn on_error(&self, mut callback: Box<dyn FnMut(String)>) {
+        let inner = self.inner.clone();
+        let onerror_callback = Closure::<dyn FnMut(_)>::new(move |e: Event| {
+            callback(format!("{:?}", e));
+            inner.shut();
+        });
+        self.inner
+            .source
+            .set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+        onerror_callback.forget();
+    }
+
+    fn on_close(&self, callback: Box<dyn FnMut()>) {
+        *self.inner.on_close.borrow_mut() = Some(callback);
+    }
+}
@@ -0,0 +1,145 @@
+pub mod event_source;
+pub mod websocket;
+
+pub use event_source::*;
+pub use websocket::*;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::error::AppError;
+use crate::utils::{MetaConfig, log};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    WebSocket,
+    EventSource,
+}
+
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+// carries frames; encryption and message types sit above it unchanged
+pub trait Transport {
+    fn kind(&self) -> TransportKind;
+    fn supports_binary(&self) -> bool;
+    fn send(&self, data: &str) -> Result<(), AppError>;
+    fn send_bytes(&self, data: &[u8]) -> Result<(), AppError>;
+    fn is_open(&self) -> bool;
+    fn buffered_amount(&self) -> u32;
+    fn close(&self);
+
+    // each call replaces the previous callback for that event
+    fn on_open(&self, callback: Box<dyn FnMut()>);
+    fn on_message(&self, callback: Box<dyn FnMut(Frame)>);
+    fn on_error(&self, callback: Box<dyn FnMut(String)>);
+    fn on_close(&self, callback: Box<dyn FnMut()>);
+}
+
+// starts on WebSocket and falls back once it repeatedly fails to open, as it does behind
+// proxies that block the upgrade; a fallback that never opens hands back to WebSocket
+pub struct TransportSelector {
+    ws_gateway: String,
+    http_gateway: String,
+    kind: Cell<TransportKind>,
+    opened: Cell<bool>,
+    failures: Cell<u32>,
+}
+
+impl TransportSelector {
+    const FAILURES_BEFORE_SWITCH: u32 = 2;
+
+    pub fn new(config: &MetaConfig) -> Self {
+        Self {
+            ws_gateway: config.ws_gateway.clone(),
+            http_gateway: config.http_gateway.clone(),
+            kind: Cell::new(TransportKind::WebSocket),
+            opened: Cell::new(false),
+            failures: Cell::new(0),
+        }
+    }
+
+    pub fn connect(&self) -> Result<Rc<dyn Transport>, AppError> {
+        self.opened.set(false);
+        Ok(match self.kind.get() {
+            TransportKind::WebSocket => Rc::new(WebSocketTransport::open(&self.ws_gateway)?),
+            TransportKind::EventSource => Rc::new(EventSourceTransport::open(&self.http_gateway)?),
+        })
+    }
+
+    pub fn opened(&self) {
+        self.opened.set(true);
+        self.failures.set(0);
+    }
+
+    pub fn closed(&self) {
+        let failed = self.kind.get();
+        if let Some(next) = self.count_failure() {
+            log(&format!(
+                "{:?} failed to open {} times, switching to {:?}",
+                failed,
+                Self::FAILURES_BEFORE_SWITCH,
+                next
+            ));
+        }
+    }
+
+    // returns the transport switched to when this close used up the last try
+    fn count_failure(&self) -> Option<TransportKind> {
+        if self.opened.get() {
+            return None;
+        }
+
+        let failures = self.failures.get() + 1;
+        if failures < Self::FAILURES_BEFORE_SWITCH {
+            self.failures.set(failures);
+            return None;
+        }
+
+        let next = match self.kind.get() {
+            TransportKind::WebSocket => TransportKind::EventSource,
+            TransportKind::EventSource => TransportKind::WebSocket,
+        };
+        self.kind.set(next);
+        self.failures.set(0);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_after_repeated_failures_to_open() {
+        let selector = TransportSelector::new(&MetaConfig::default());
+        assert_eq!(selector.count_failure(), None);
+        assert_eq!(selector.count_failure(), Some(TransportKind::EventSource));
+        assert_eq!(selector.kind.get(), TransportKind::EventSource);
+
+        assert_eq!(selector.count_failure(), None);
+        assert_eq!(selector.count_failure(), Some(TransportKind::WebSocket));
+    }
+
+    #[test]
+    fn keeps_a_transport_that_opened() {
+        let selector = TransportSelector::new(&MetaConfig::default());
+        assert_eq!(selector.count_failure(), None);
+        selector.opened();
+        assert_eq!(selector.count_failure(), None);
+        assert_eq!(selector.count_failure(), None);
+        assert_eq!(selector.kind.get(), TransportKind::WebSocket);
+    }
+
+    #[test]
+    fn an_open_resets_the_failure_count() {
+        let selector = TransportSelector::new(&MetaConfig::default());
+        assert_eq!(selector.count_failure(), None);
+        selector.opened();
+        selector.opened.set(false);
+        assert_eq!(selector.count_failure(), None);
+        assert_eq!(selector.kind.get(), TransportKind::WebSocket);
+    }
+}
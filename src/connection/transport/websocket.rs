@@ -0,0 +1,86 @@
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{BinaryType, ErrorEvent, MessageEvent, WebSocket};
+
+use super::{Frame, Transport, TransportKind};
+use crate::error::AppError;
+
+pub struct WebSocketTransport {
+    ws: WebSocket,
+}
+
+impl WebSocketTransport {
+    pub fn open(url: &str) -> Result<Self, AppError> {
+        let ws = WebSocket::new(url)
+            .map_err(|e| AppError::WebSocketError(e.as_string().unwrap_or_default()))?;
+        ws.set_binary_type(BinaryType::Arraybuffer);
+        Ok(Self { ws })
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::WebSocket
+    }
+
+    fn supports_binary(&self) -> bool {
+        true
+    }
+
+    fn send(&self, data: &str) -> Result<(), AppError> {
+        self.ws.send_with_str(data).map_err(AppError::from)
+    }
+
+    fn send_bytes(&self, data: &[u8]) -> Result<(), AppError> {
+        self.ws.send_with_u8_array(data).map_err(AppError::from)
+    }
+
+    fn is_open(&self) -> bool {
+        self.ws.ready_state() == WebSocket::OPEN
+    }
+
+    fn buffered_amount(&self) -> u32 {
+        self.ws.buffered_amount()
+    }
+
+    fn close(&self) {
+        let _ = self.ws.close();
+    }
+
+    fn on_open(&self, callback: Box<dyn FnMut()>) {
+        let onopen_callback = Closure::wrap(callback);
+        self.ws
+            .set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+        onopen_callback.forget();
+    }
+
+    fn on_message(&self, mut callback: Box<dyn FnMut(Frame)>) {
+        let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
+            let data = e.data();
+            if let Some(buffer) = data.dyn_ref::<js_sys::ArrayBuffer>() {
+                callback(Frame::Binary(js_sys::Uint8Array::new(buffer).to_vec()));
+            } else if let Ok(txt) = data.dyn_into::<js_sys::JsString>() {
+                callback(Frame::Text(txt.into()));
+            }
+        });
+        self.ws
+            .set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+        onmessage_callback.forget();
+    }
+
+    fn on_error(&self, mut callback: Box<dyn FnMut(String)>) {
+        let onerror_callback = Closure::<dyn FnMut(_)>::new(move |e: ErrorEvent| {
+            callback(format!("{:?}", e));
+        });
+        self.ws
+            .set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+        onerror_callback.forget();
+    }
+
+    fn on_close(&self, callback: Box<dyn FnMut()>) {
+        let onclose_callback = Closure::wrap(callback);
+        self.ws
+            .set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+        onclose_callback.forget();
+    }
+}
@@ -0,0 +1,227 @@
+use base64::{Engine as _, engine::general_purpose};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Element, File, HtmlFormElement, HtmlInputElement};
+
+use crate::connection::{ClientMessage, Crypto, Messaging, Socket};
+use crate::error::AppError;
+use crate::utils::EventDispatcher;
+
+struct PendingUpload {
+    field: String,
+    file: File,
+    input: Element,
+}
+
+#[derive(Default)]
+pub struct Uploads {
+    pending: HashMap<String, PendingUpload>,
+}
+
+impl Uploads {
+    pub const MODE_ATTR: &'static str = "data-upload";
+    pub const IDS_ATTR: &'static str = "data-upload-ids";
+
+    const CHUNK_SIZE: f64 = 64.0 * 1024.0;
+    const MAX_BUFFERED_BYTES: u32 = 1024 * 1024;
+    const DRAIN_POLL_MS: i32 = 50;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn select(&mut self, input: &HtmlInputElement) -> Vec<String> {
+        if let Some(previous) = input.get_attribute(Self::IDS_ATTR) {
+            for upload_id in previous.split(',') {
+                self.pending.remove(upload_id);
+            }
+        }
+
+        let Some(files) = input.files() else {
+            let _ = input.remove_attribute(Self::IDS_ATTR);
+            return Vec::new();
+        };
+
+        let upload_ids: Vec<String> = (0..files.length())
+            .filter_map(|i| files.item(i))
+            .map(|file| {
+                let upload_id = Self::generate_id();
+                self.pending.insert(
+                    upload_id.clone(),
+                    PendingUpload {
+                        field: input.name(),
+                        file,
+                        input: input.clone().into(),
+                    },
+                );
+                upload_id
+            })
+            .collect();
+
+        let _ = input.set_attribute(Self::IDS_ATTR, &upload_ids.join(","));
+        upload_ids
+    }
+
+    pub fn request(&self, ws: &Socket, crypto: &Crypto, upload_ids: &[String]) {
+        for upload_id in upload_ids {
+            if let Some(upload) = self.pending.get(upload_id) {
+                Messaging::send_encrypted_message(
+                    ws,
+                    &ClientMessage::UploadStart {
+                        upload_id: upload_id.clone(),
+                        field: upload.field.clone(),
+                        name: upload.file.name(),
+                        mime_type: upload.file.type_(),
+                        size: upload.file.size(),
+                    },
+                    crypto,
+                );
+            }
+        }
+    }
+
+    pub fn request_form(&self, ws: &Socket, crypto: &Crypto, form: &HtmlFormElement) {
+        let selector = format!("input[type='file'][{}='submit']", Self::MODE_ATTR);
+        let Ok(inputs) = form.query_selector_all(&selector) else {
+            return;
+        };
+
+        for i in 0..inputs.length() {
+            let upload_ids = inputs
+                .get(i)
+                .and_then(|node| node.dyn_into::<Element>().ok())
+                .and_then(|input| input.get_attribute(Self::IDS_ATTR))
+                .unwrap_or_default();
+            let upload_ids: Vec<String> = upload_ids
+                .split(',')
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect();
+            self.request(ws, crypto, &upload_ids);
+        }
+    }
+
+    pub fn accept(
+        uploads: &Rc<RefCell<Self>>,
+        ws: &Socket,
+        crypto: &Rc<RefCell<Crypto>>,
+        upload_id: &str,
+    ) -> Result<(), AppError> {
+        let upload = uploads
+            .borrow_mut()
+            .pending
+            .remove(upload_id)
+            .ok_or_else(|| AppError::InvalidState(format!("Unknown upload: {}", upload_id)))?;
+
+        let ws = ws.clone();
+        let crypto = crypto.clone();
+        let upload_id = upload_id.to_string();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            match Self::stream(&ws, &crypto, &upload_id, &upload).await {
+                Ok(()) => EventDispatcher::dispatch(
+                    &upload.input,
+                    "quillion:upload-complete",
+                    &serde_json::json!({ "uploadId": upload_id, "name": upload.file.name() }),
+                ),
+                Err(e) => EventDispatcher::dispatch(
+                    &upload.input,
+                    "quillion:upload-failed",
+                    &serde_json::json!({ "uploadId": upload_id, "error": e.to_string() }),
+                ),
+            }
+        });
+        Ok(())
+    }
+
+    pub fn reject(&mut self, upload_id: &str, error: Option<&str>) {
+        if let Some(upload) = self.pending.remove(upload_id) {
+            EventDispatcher::dispatch(
+                &upload.input,
+                "quillion:upload-rejected",
+                &serde_json::json!({
+                    "uploadId": upload_id,
+                    "name": upload.file.name(),
+                    "error": error
+                }),
+            );
+        }
+    }
+
+    async fn stream(
+        ws: &Socket,
+        crypto: &Rc<RefCell<Crypto>>,
+        upload_id: &str,
+        upload: &PendingUpload,
+    ) -> Result<(), AppError> {
+        let total = upload.file.size();
+        let mut offset = 0.0;
+        // chunks belong to the server session that accepted the upload
+        let session_socket = ws.current();
+
+        while offset < total {
+            if !ws.is_open() || !Rc::ptr_eq(&ws.current(), &session_socket) {
+                return Err(AppError::WebSocketError(
+                    "Connection closed during upload".to_string(),
+                ));
+            }
+            Self::wait_for_drain(ws).await;
+
+            let end = (offset + Self::CHUNK_SIZE).min(total);
+            let chunk = upload.file.slice_with_f64_and_f64(offset, end)?;
+            let buffer = JsFuture::from(chunk.array_buffer()).await?;
+            let bytes = js_sys::Uint8Array::new(&buffer).to_vec();
+
+            Messaging::send_encrypted_message(
+                ws,
+                &ClientMessage::UploadChunk {
+                    upload_id: upload_id.to_string(),
+                    offset,
+                    data: general_purpose::STANDARD.encode(bytes),
+                },
+                &crypto.borrow(),
+            );
+
+            offset = end;
+            EventDispatcher::dispatch(
+                &upload.input,
+                "quillion:upload-progress",
+                &serde_json::json!({ "uploadId": upload_id, "loaded": offset, "total": total }),
+            );
+        }
+
+        Messaging::send_encrypted_message(
+            ws,
+            &ClientMessage::UploadComplete {
+                upload_id: upload_id.to_string(),
+            },
+            &crypto.borrow(),
+        );
+        Ok(())
+    }
+
+    async fn wait_for_drain(ws: &Socket) {
+        while ws.buffered_amount() > Self::MAX_BUFFERED_BYTES {
+            let delay = js_sys::Promise::new(&mut |resolve, _| {
+                if let Some(window) = web_sys::window() {
+                    let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                        &resolve,
+                        Self::DRAIN_POLL_MS,
+                    );
+                }
+            });
+            let _ = JsFuture::from(delay).await;
+        }
+    }
+
+    fn generate_id() -> String {
+        let mut bytes = [0u8; 8];
+        OsRng.fill_bytes(&mut bytes);
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
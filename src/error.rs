@@ -23,6 +23,18 @@ pub enum AppError {
 
     #[error("Cryptography error: {0}")]
     CryptoError(String),
+
+    #[error("Rejected CSS: {0}")]
+    InvalidCss(String),
+
+    #[error("Transport error: {0}")]
+    TransportError(String),
+
+    #[error("Encoding error: {0}")]
+    EncodingError(String),
+
+    #[error("Protocol mismatch: client speaks version {client}, server speaks version {server}")]
+    ProtocolMismatch { client: u32, server: u32 },
 }
 
 impl From<AppError> for JsValue {
@@ -16,9 +16,9 @@ pub fn start() -> Result<(), JsValue> {
     let config = MetaConfig::from_document()?;
 
     let connection =
-        ClientConnection::new(&config.ws_gateway).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        ClientConnection::new(&config).map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-    let vdom = VirtualDom::new(connection.get_crypto_ref());
+    let vdom = VirtualDom::new(connection.get_crypto_ref(), connection.get_uploads_ref());
 
     connection
         .start(vdom)
@@ -4,6 +4,15 @@ use web_sys::{Document, window};
 #[derive(Debug, Clone)]
 pub struct MetaConfig {
     pub ws_gateway: String,
+    pub http_gateway: String,
+    pub reconnect_delay_ms: u32,
+    pub reconnect_max_delay_ms: u32,
+    pub reconnect_max_retries: u32,
+    // pings servers that do not list the heartbeat feature, for deployments known to answer
+    pub heartbeat: bool,
+    pub heartbeat_interval_ms: u32,
+    pub heartbeat_timeout_ms: u32,
+    pub report_latency: bool,
 }
 
 impl MetaConfig {
@@ -21,7 +30,29 @@ impl MetaConfig {
             Self::build_ws_url_from_location(1337)?
         };
 
-        Ok(Self { ws_gateway })
+        let http_gateway = Self::get_meta_content(&document, "http-gateway")
+            .unwrap_or_else(|| Self::http_url_from_ws(&ws_gateway));
+
+        let defaults = Self::default();
+        let number = |name: &str, default: u32| {
+            Self::get_meta_content(&document, name)
+                .and_then(|content| content.trim().parse().ok())
+                .unwrap_or(default)
+        };
+
+        Ok(Self {
+            ws_gateway,
+            http_gateway,
+            reconnect_delay_ms: number("reconnect-delay", defaults.reconnect_delay_ms),
+            reconnect_max_delay_ms: number("reconnect-max-delay", defaults.reconnect_max_delay_ms),
+            reconnect_max_retries: number("reconnect-retries", defaults.reconnect_max_retries),
+            heartbeat: Self::get_meta_content(&document, "heartbeat")
+                .is_some_and(|content| content != "false"),
+            heartbeat_interval_ms: number("heartbeat-interval", defaults.heartbeat_interval_ms),
+            heartbeat_timeout_ms: number("heartbeat-timeout", defaults.heartbeat_timeout_ms),
+            report_latency: Self::get_meta_content(&document, "report-latency")
+                .is_some_and(|content| content != "false"),
+        })
     }
 
     fn get_meta_content(document: &Document, name: &str) -> Option<String> {
@@ -43,6 +74,15 @@ impl MetaConfig {
             })
     }
 
+    // the fallback transport talks to the same gateway over plain HTTP(S)
+    fn http_url_from_ws(ws_gateway: &str) -> String {
+        match ws_gateway.split_once("://") {
+            Some(("wss", rest)) => format!("https://{}", rest),
+            Some(("ws", rest)) => format!("http://{}", rest),
+            _ => ws_gateway.to_string(),
+        }
+    }
+
     fn build_ws_url_from_location(port: u16) -> Result<String, JsValue> {
         let window = window().ok_or_else(|| JsValue::from_str("No window"))?;
         let location = window.location();
@@ -58,6 +98,14 @@ impl Default for MetaConfig {
     fn default() -> Self {
         Self {
             ws_gateway: "ws://localhost:1337".into(),
+            http_gateway: "http://localhost:1337".into(),
+            reconnect_delay_ms: 500,
+            reconnect_max_delay_ms: 30_000,
+            reconnect_max_retries: 12,
+            heartbeat: false,
+            heartbeat_interval_ms: 25_000,
+            heartbeat_timeout_ms: 10_000,
+            report_latency: false,
         }
     }
 }
@@ -0,0 +1,17 @@
+use web_sys::{CustomEvent, CustomEventInit, EventTarget};
+
+pub struct EventDispatcher;
+
+impl EventDispatcher {
+    pub fn dispatch(target: &EventTarget, event_name: &str, detail: &serde_json::Value) {
+        let init = CustomEventInit::new();
+        init.set_bubbles(true);
+        if let Ok(detail) = js_sys::JSON::parse(&detail.to_string()) {
+            init.set_detail(&detail);
+        }
+
+        if let Ok(event) = CustomEvent::new_with_event_init_dict(event_name, &init) {
+            let _ = target.dispatch_event(&event);
+        }
+    }
+}
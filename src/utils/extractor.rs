@@ -1,5 +1,7 @@
 use wasm_bindgen::JsCast;
 
+use crate::utils::{FormSerializer, FormValidator};
+
 pub struct EventDataExtractor;
 
 impl EventDataExtractor {
@@ -53,58 +55,51 @@ impl EventDataExtractor {
         }
     }
 
-    pub fn extract_form_data(form: &web_sys::HtmlFormElement) -> Option<serde_json::Value> {
-        let mut form_data = serde_json::Map::new();
-
-        if let Ok(elements) = form.query_selector_all("input, select, textarea") {
-            for i in 0..elements.length() {
-                if let Some(element) = elements.get(i) {
-                    let element_clone = element.clone();
-
-                    if let Ok(input) = element_clone.dyn_into::<web_sys::HtmlInputElement>() {
-                        let name = input.name();
-                        if !name.is_empty() {
-                            match input.type_().as_str() {
-                                "checkbox" => {
-                                    form_data
-                                        .insert(name, serde_json::Value::Bool(input.checked()));
-                                }
-                                "radio" => {
-                                    if input.checked() {
-                                        form_data
-                                            .insert(name, serde_json::Value::String(input.value()));
-                                    }
-                                }
-                                _ => {
-                                    form_data
-                                        .insert(name, serde_json::Value::String(input.value()));
-                                }
-                            }
-                        }
-                    } else {
-                        let element_clone = element.clone();
-                        if let Ok(select) = element_clone.dyn_into::<web_sys::HtmlSelectElement>() {
-                            let name = select.name();
-                            if !name.is_empty() {
-                                form_data.insert(name, serde_json::Value::String(select.value()));
-                            }
-                        } else {
-                            let element_clone = element.clone();
-                            if let Ok(textarea) =
-                                element_clone.dyn_into::<web_sys::HtmlTextAreaElement>()
-                            {
-                                let name = textarea.name();
-                                if !name.is_empty() {
-                                    form_data
-                                        .insert(name, serde_json::Value::String(textarea.value()));
-                                }
-                            }
-                        }
-                    }
-                }
+    pub fn extract_global_data(
+        window: &web_sys::Window,
+        event: &web_sys::Event,
+        event_type: &str,
+    ) -> Option<serde_json::Value> {
+        match event_type {
+            "resize" => Some(serde_json::json!({
+                "innerWidth": window.inner_width().ok().and_then(|w| w.as_f64()),
+                "innerHeight": window.inner_height().ok().and_then(|h| h.as_f64())
+            })),
+            "scroll" => Some(serde_json::json!({
+                "scrollX": window.scroll_x().unwrap_or_default(),
+                "scrollY": window.scroll_y().unwrap_or_default()
+            })),
+            "visibilitychange" => window.document().map(|document| {
+                let hidden = document.hidden();
+                serde_json::json!({
+                    "visibilityState": if hidden { "hidden" } else { "visible" },
+                    "hidden": hidden
+                })
+            }),
+            "online" | "offline" => Some(serde_json::json!({
+                "onLine": window.navigator().on_line()
+            })),
+            "focus" | "blur" => window.document().map(|document| {
+                serde_json::json!({
+                    "hasFocus": document.has_focus().unwrap_or_default()
+                })
+            }),
+            "keydown" | "keyup" | "keypress" => Self::extract_keyboard_data(event),
+            "click" | "dblclick" | "mousedown" | "mouseup" | "mousemove" => {
+                Self::extract_mouse_data(event)
             }
+            _ => None,
         }
+    }
+
+    pub fn extract_form_data(
+        form: &web_sys::HtmlFormElement,
+        submitter: Option<&web_sys::HtmlElement>,
+    ) -> Option<serde_json::Value> {
+        Some(FormSerializer::serialize(form, submitter))
+    }
 
-        Some(serde_json::Value::Object(form_data))
+    pub fn extract_form_validity(form: &web_sys::HtmlFormElement) -> serde_json::Value {
+        FormValidator::report(form)
     }
 }
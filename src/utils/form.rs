@@ -0,0 +1,434 @@
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::JsCast;
+use web_sys::{
+    Element, HtmlElement, HtmlFormElement, HtmlInputElement, HtmlOptionElement, HtmlSelectElement,
+    HtmlTextAreaElement, ValidityState,
+};
+
+use crate::connection::Uploads;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Coercion {
+    number: bool,
+    date: bool,
+    checkbox: bool,
+}
+
+impl Coercion {
+    const COERCE_ATTR: &'static str = "data-coerce";
+
+    fn from_form(form: &HtmlFormElement) -> Self {
+        match form.get_attribute(Self::COERCE_ATTR) {
+            None => Self::default(),
+            Some(value) if value.trim().is_empty() || value.trim() == "true" => Self {
+                number: true,
+                date: true,
+                checkbox: true,
+            },
+            Some(value) => {
+                let kinds: Vec<&str> = value.split(',').map(str::trim).collect();
+                Self {
+                    number: kinds.contains(&"number"),
+                    date: kinds.contains(&"date"),
+                    checkbox: kinds.contains(&"checkbox"),
+                }
+            }
+        }
+    }
+}
+
+pub struct FormSerializer {
+    coercion: Coercion,
+    entries: Vec<(String, serde_json::Value)>,
+    controls_per_name: HashMap<String, usize>,
+    array_names: HashSet<String>,
+}
+
+impl FormSerializer {
+    const SKIPPED_INPUT_TYPES: [&'static str; 4] = ["submit", "reset", "button", "image"];
+    const DATE_INPUT_TYPES: [&'static str; 5] = ["date", "datetime-local", "month", "week", "time"];
+
+    pub fn serialize(form: &HtmlFormElement, submitter: Option<&HtmlElement>) -> serde_json::Value {
+        let mut serializer = Self {
+            coercion: Coercion::from_form(form),
+            entries: Vec::new(),
+            controls_per_name: HashMap::new(),
+            array_names: HashSet::new(),
+        };
+
+        let elements = form.elements();
+        let controls: Vec<Element> = (0..elements.length())
+            .filter_map(|i| elements.item(i))
+            .filter(|element| !element.matches(":disabled").unwrap_or(false))
+            .collect();
+
+        for control in &controls {
+            serializer.count_control(control);
+        }
+        // like FormData, the submitter's entry goes where the submitter sits in the form
+        let mut submitter_pushed = false;
+        for control in &controls {
+            match submitter {
+                Some(submitter) if submitter.is_same_node(Some(control)) => {
+                    serializer.push_submitter(submitter);
+                    submitter_pushed = true;
+                }
+                _ => serializer.push_control(control),
+            }
+        }
+        if let Some(submitter) = submitter.filter(|_| !submitter_pushed) {
+            serializer.push_submitter(submitter);
+        }
+
+        serializer.finish()
+    }
+
+    fn count_control(&mut self, control: &Element) {
+        let name = control.get_attribute("name").unwrap_or_default();
+        if name.is_empty() {
+            return;
+        }
+
+        if let Some(input) = control.dyn_ref::<HtmlInputElement>() {
+            let input_type = input.type_();
+            // a radio group yields at most one value, so it never turns its name into a list
+            if input_type == "radio" || Self::SKIPPED_INPUT_TYPES.contains(&input_type.as_str()) {
+                return;
+            }
+            if input_type == "file" && input.multiple() {
+                self.array_names.insert(name.clone());
+            }
+        } else if let Some(select) = control.dyn_ref::<HtmlSelectElement>() {
+            if select.multiple() {
+                self.array_names.insert(name.clone());
+            }
+        } else if control.dyn_ref::<HtmlTextAreaElement>().is_none() {
+            return;
+        }
+
+        if name.ends_with("[]") {
+            self.array_names.insert(name.clone());
+        }
+        *self.controls_per_name.entry(name).or_default() += 1;
+    }
+
+    fn push_control(&mut self, control: &Element) {
+        if let Some(input) = control.dyn_ref::<HtmlInputElement>() {
+            self.push_input(input);
+        } else if let Some(select) = control.dyn_ref::<HtmlSelectElement>() {
+            let name = select.name();
+            if name.is_empty() {
+                return;
+            }
+            let options = select.selected_options();
+            for i in 0..options.length() {
+                if let Some(option) = options
+                    .item(i)
+                    .and_then(|o| o.dyn_into::<HtmlOptionElement>().ok())
+                {
+                    self.push(&name, serde_json::Value::String(option.value()));
+                }
+            }
+        } else if let Some(textarea) = control.dyn_ref::<HtmlTextAreaElement>() {
+            let name = textarea.name();
+            if !name.is_empty() {
+                self.push(&name, serde_json::Value::String(textarea.value()));
+            }
+        }
+    }
+
+    fn push_input(&mut self, input: &HtmlInputElement) {
+        let name = input.name();
+        let input_type = input.type_();
+        if name.is_empty() || Self::SKIPPED_INPUT_TYPES.contains(&input_type.as_str()) {
+            return;
+        }
+
+        match input_type.as_str() {
+            // with coercion every checkbox reports its state, so unchecked ones are not lost
+            "checkbox" if self.coercion.checkbox => {
+                self.push(&name, serde_json::Value::Bool(input.checked()));
+            }
+            "checkbox" | "radio" => {
+                if input.checked() {
+                    self.push(&name, serde_json::Value::String(input.value()));
+                }
+            }
+            "file" => {
+                let files = input.files();
+                let upload_ids = input.get_attribute(Uploads::IDS_ATTR).unwrap_or_default();
+                let mut upload_ids = upload_ids.split(',').filter(|id| !id.is_empty());
+                for i in 0..files.as_ref().map_or(0, |f| f.length()) {
+                    if let Some(file) = files.as_ref().and_then(|f| f.item(i)) {
+                        self.push(
+                            &name,
+                            serde_json::json!({
+                                "name": file.name(),
+                                "size": file.size(),
+                                "type": file.type_(),
+                                "uploadId": upload_ids.next()
+                            }),
+                        );
+                    }
+                }
+            }
+            "number" | "range" if self.coercion.number => {
+                self.push(&name, Self::as_number(input));
+            }
+            kind if self.coercion.date && Self::DATE_INPUT_TYPES.contains(&kind) => {
+                self.push(&name, Self::as_number(input));
+            }
+            _ => {
+                self.push(&name, serde_json::Value::String(input.value()));
+            }
+        }
+    }
+
+    fn push_submitter(&mut self, submitter: &HtmlElement) {
+        let name = submitter.get_attribute("name").unwrap_or_default();
+        if name.is_empty() {
+            return;
+        }
+        let value = submitter.get_attribute("value").unwrap_or_default();
+        self.push(&name, serde_json::Value::String(value));
+    }
+
+    fn as_number(input: &HtmlInputElement) -> serde_json::Value {
+        let number = input.value_as_number();
+        if number.is_finite() {
+            serde_json::json!(number)
+        } else {
+            serde_json::Value::Null
+        }
+    }
+
+    fn is_list(&self, name: &str) -> bool {
+        self.array_names.contains(name)
+            || self
+                .controls_per_name
+                .get(name)
+                .is_some_and(|count| *count > 1)
+    }
+
+    fn push(&mut self, name: &str, value: serde_json::Value) {
+        self.entries.push((name.to_string(), value));
+    }
+
+    fn finish(self) -> serde_json::Value {
+        let mut grouped: IndexMap<&str, Vec<serde_json::Value>> = IndexMap::new();
+        for (name, value) in &self.entries {
+            grouped.entry(name).or_default().push(value.clone());
+        }
+
+        let mut form_data = serde_json::Map::new();
+        for (name, mut values) in grouped {
+            let value = if values.len() > 1 || self.is_list(name) {
+                serde_json::Value::Array(values)
+            } else {
+                values.pop().unwrap_or(serde_json::Value::Null)
+            };
+            form_data.insert(name.to_string(), value);
+        }
+
+        // list-shaped names stay lists even when nothing in them was selected
+        for name in self.controls_per_name.keys().chain(self.array_names.iter()) {
+            if self.is_list(name) && !form_data.contains_key(name) {
+                form_data.insert(name.clone(), serde_json::Value::Array(Vec::new()));
+            }
+        }
+
+        serde_json::Value::Object(form_data)
+    }
+}
+
+pub struct FormValidator;
+
+impl FormValidator {
+    pub const BLOCK_ATTR: &'static str = "data-validate";
+
+    pub fn blocks_invalid(form: &HtmlFormElement) -> bool {
+        form.has_attribute(Self::BLOCK_ATTR)
+    }
+
+    // reads each control's validity state; form.check_validity() would fire invalid events
+    pub fn report(form: &HtmlFormElement) -> serde_json::Value {
+        let elements = form.elements();
+        let controls = (0..elements.length())
+            .filter_map(|i| elements.item(i))
+            .filter_map(|control| Self::control_validity(&control))
+            .map(|(name, validity, message)| (name, Self::describe(&validity, message)));
+        Self::collect(controls)
+    }
+
+    // unnamed controls still count toward the form, they just have no field to report under
+    fn collect(
+        controls: impl IntoIterator<Item = (String, serde_json::Value)>,
+    ) -> serde_json::Value {
+        let mut fields = serde_json::Map::new();
+        let mut form_valid = true;
+
+        for (name, description) in controls {
+            let valid = description["valid"] != false;
+            form_valid &= valid;
+            if name.is_empty() {
+                continue;
+            }
+
+            // grouped controls share a name, so an invalid member wins over a valid one
+            let already_invalid = fields
+                .get(&name)
+                .is_some_and(|entry| entry["valid"] == false);
+            if !already_invalid {
+                fields.insert(name, description);
+            }
+        }
+
+        serde_json::json!({
+            "valid": form_valid,
+            "fields": fields
+        })
+    }
+
+    fn control_validity(control: &Element) -> Option<(String, ValidityState, String)> {
+        let (name, validity, message) = if let Some(input) = control.dyn_ref::<HtmlInputElement>() {
+            if !input.will_validate() {
+                return None;
+            }
+            (input.name(), input.validity(), input.validation_message())
+        } else if let Some(select) = control.dyn_ref::<HtmlSelectElement>() {
+            if !select.will_validate() {
+                return None;
+            }
+            (
+                select.name(),
+                select.validity(),
+                select.validation_message(),
+            )
+        } else if let Some(textarea) = control.dyn_ref::<HtmlTextAreaElement>() {
+            if !textarea.will_validate() {
+                return None;
+            }
+            (
+                textarea.name(),
+                textarea.validity(),
+                textarea.validation_message(),
+            )
+        } else {
+            return None;
+        };
+
+        Some((name, validity, message.unwrap_or_default()))
+    }
+
+    fn describe(validity: &ValidityState, message: String) -> serde_json::Value {
+        serde_json::json!({
+            "valid": validity.valid(),
+            "valueMissing": validity.value_missing(),
+            "typeMismatch": validity.type_mismatch(),
+            "patternMismatch": validity.pattern_mismatch(),
+            "tooLong": validity.too_long(),
+            "tooShort": validity.too_short(),
+            "rangeUnderflow": validity.range_underflow(),
+            "rangeOverflow": validity.range_overflow(),
+            "stepMismatch": validity.step_mismatch(),
+            "badInput": validity.bad_input(),
+            "customError": validity.custom_error(),
+            "validationMessage": message
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn serializer(
+        entries: &[(&str, serde_json::Value)],
+        controls: &[(&str, usize)],
+    ) -> FormSerializer {
+        FormSerializer {
+            coercion: Coercion::default(),
+            entries: entries
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            controls_per_name: controls
+                .iter()
+                .map(|(name, count)| (name.to_string(), *count))
+                .collect(),
+            array_names: controls
+                .iter()
+                .filter(|(name, _)| name.ends_with("[]"))
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn single_values_stay_scalar() {
+        let form = serializer(
+            &[("name", json!("Ada")), ("agree", json!(true))],
+            &[("name", 1)],
+        );
+        assert_eq!(form.finish(), json!({ "name": "Ada", "agree": true }));
+    }
+
+    #[test]
+    fn repeated_names_group_in_order() {
+        let form = serializer(
+            &[
+                ("tag", json!("a")),
+                ("other", json!("x")),
+                ("tag", json!("b")),
+            ],
+            &[("tag", 1), ("other", 1)],
+        );
+        assert_eq!(form.finish(), json!({ "tag": ["a", "b"], "other": "x" }));
+    }
+
+    fn field(valid: bool) -> serde_json::Value {
+        json!({ "valid": valid, "valueMissing": !valid, "validationMessage": "" })
+    }
+
+    #[test]
+    fn reports_a_valid_form() {
+        let report = FormValidator::collect([("name".to_string(), field(true))]);
+        assert_eq!(
+            report,
+            json!({ "valid": true, "fields": { "name": field(true) } })
+        );
+    }
+
+    #[test]
+    fn invalid_group_members_win() {
+        let report = FormValidator::collect([
+            ("pick".to_string(), field(true)),
+            ("pick".to_string(), field(false)),
+            ("pick".to_string(), field(true)),
+        ]);
+        assert_eq!(
+            report,
+            json!({ "valid": false, "fields": { "pick": field(false) } })
+        );
+    }
+
+    #[test]
+    fn unnamed_controls_only_affect_the_form() {
+        let report = FormValidator::collect([
+            ("name".to_string(), field(true)),
+            (String::new(), field(false)),
+        ]);
+        assert_eq!(
+            report,
+            json!({ "valid": false, "fields": { "name": field(true) } })
+        );
+    }
+
+    #[test]
+    fn list_names_stay_lists() {
+        let form = serializer(&[("ids[]", json!("1"))], &[("ids[]", 1), ("pick", 2)]);
+        assert_eq!(form.finish(), json!({ "ids[]": ["1"], "pick": [] }));
+    }
+}
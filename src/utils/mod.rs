@@ -1,7 +1,13 @@
 pub mod config;
+pub mod dispatch;
 pub mod extractor;
+pub mod form;
 pub mod formatter;
+pub mod preferences;
 
 pub use config::*;
+pub use dispatch::*;
 pub use extractor::*;
+pub use form::*;
 pub use formatter::*;
+pub use preferences::*;
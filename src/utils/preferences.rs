@@ -0,0 +1,56 @@
+use serde::Serialize;
+use web_sys::Window;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Preferences {
+    pub color_scheme: &'static str,
+    pub reduced_motion: bool,
+    pub contrast: &'static str,
+    pub forced_colors: bool,
+    pub device_pixel_ratio: f64,
+}
+
+impl Preferences {
+    // every query whose result feeds a field; a change on any of them triggers a report
+    pub const WATCHED_QUERIES: [&'static str; 5] = [
+        "(prefers-color-scheme: dark)",
+        "(prefers-reduced-motion: reduce)",
+        "(prefers-contrast: more)",
+        "(prefers-contrast: less)",
+        "(forced-colors: active)",
+    ];
+
+    pub fn from_window(window: &Window) -> Self {
+        let matches = |query: &str| {
+            window
+                .match_media(query)
+                .ok()
+                .flatten()
+                .is_some_and(|list| list.matches())
+        };
+
+        let contrast = if matches("(prefers-contrast: more)") {
+            "more"
+        } else if matches("(prefers-contrast: less)") {
+            "less"
+        } else {
+            "no-preference"
+        };
+
+        Self {
+            color_scheme: if matches("(prefers-color-scheme: dark)") {
+                "dark"
+            } else {
+                "light"
+            },
+            reduced_motion: matches("(prefers-reduced-motion: reduce)"),
+            contrast,
+            forced_colors: matches("(forced-colors: active)"),
+            device_pixel_ratio: window.device_pixel_ratio(),
+        }
+    }
+
+    pub fn pixel_ratio_query(window: &Window) -> String {
+        format!("(resolution: {}dppx)", window.device_pixel_ratio())
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{Element, HtmlElement};
+
+use crate::utils::EventDispatcher;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JsCommand {
+    Show {
+        to: Option<String>,
+        display: Option<String>,
+    },
+    Hide {
+        to: Option<String>,
+    },
+    ToggleClass {
+        to: Option<String>,
+        class: String,
+    },
+    AddClass {
+        to: Option<String>,
+        class: String,
+    },
+    RemoveClass {
+        to: Option<String>,
+        class: String,
+    },
+    SetAttr {
+        to: Option<String>,
+        name: String,
+        value: String,
+    },
+    Focus {
+        to: Option<String>,
+    },
+    Dispatch {
+        to: Option<String>,
+        event: String,
+        #[serde(default)]
+        detail: serde_json::Value,
+    },
+    Transition {
+        to: Option<String>,
+        class: String,
+        time: Option<i32>,
+    },
+    Push {
+        id: String,
+    },
+}
+
+impl JsCommand {
+    pub const ATTR_PREFIX: &'static str = "data-js-";
+    const DEFAULT_TRANSITION_MS: i32 = 200;
+
+    pub fn parse(value: &str) -> Result<Vec<Self>, serde_json::Error> {
+        serde_json::from_str(value)
+    }
+
+    pub fn execute_all(commands: &[Self], origin: &Element, push: &mut dyn FnMut(&str)) {
+        for command in commands {
+            command.execute(origin, push);
+        }
+    }
+
+    fn execute(&self, origin: &Element, push: &mut dyn FnMut(&str)) {
+        match self {
+            Self::Show { to, display } => {
+                for el in Self::targets(origin, to) {
+                    LocalEdits::update(&el, |edits| {
+                        edits.display = Some(display.clone().unwrap_or_else(|| "block".into()))
+                    });
+                }
+            }
+            Self::Hide { to } => {
+                for el in Self::targets(origin, to) {
+                    LocalEdits::update(&el, |edits| edits.display = Some("none".into()));
+                }
+            }
+            Self::ToggleClass { to, class } => {
+                for el in Self::targets(origin, to) {
+                    let class_list = el.class_list();
+                    LocalEdits::update(&el, |edits| {
+                        for name in class.split_whitespace() {
+                            if class_list.contains(name) {
+                                edits.remove_class(name);
+                            } else {
+                                edits.add_class(name);
+                            }
+                        }
+                    });
+                }
+            }
+            Self::AddClass { to, class } => {
+                for el in Self::targets(origin, to) {
+                    LocalEdits::update(&el, |edits| {
+                        class
+                            .split_whitespace()
+                            .for_each(|name| edits.add_class(name))
+                    });
+                }
+            }
+            Self::RemoveClass { to, class } => {
+                for el in Self::targets(origin, to) {
+                    LocalEdits::update(&el, |edits| {
+                        class
+                            .split_whitespace()
+                            .for_each(|name| edits.remove_class(name))
+                    });
+                }
+            }
+            Self::SetAttr { to, name, value } => {
+                for el in Self::targets(origin, to) {
+                    LocalEdits::update(&el, |edits| {
+                        edits.attributes.insert(name.clone(), value.clone());
+                    });
+                }
+            }
+            Self::Focus { to } => {
+                if let Some(el) = Self::targets(origin, to)
+                    .into_iter()
+                    .find_map(|el| el.dyn_into::<HtmlElement>().ok())
+                {
+                    let _ = el.focus();
+                }
+            }
+            Self::Dispatch { to, event, detail } => {
+                for el in Self::targets(origin, to) {
+                    EventDispatcher::dispatch(&el, event, detail);
+                }
+            }
+            Self::Transition { to, class, time } => {
+                for el in Self::targets(origin, to) {
+                    Self::transition(&el, class, time.unwrap_or(Self::DEFAULT_TRANSITION_MS));
+                }
+            }
+            Self::Push { id } => push(id),
+        }
+    }
+
+    // transition classes are short-lived, so they are not kept as local edits
+    fn transition(element: &Element, class: &str, time: i32) {
+        let class_list = element.class_list();
+        for name in class.split_whitespace() {
+            let _ = class_list.add_1(name);
+        }
+
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        let element = element.clone();
+        let class = class.to_string();
+        let cleanup = Closure::once_into_js(move || {
+            let class_list = element.class_list();
+            for name in class.split_whitespace() {
+                let _ = class_list.remove_1(name);
+            }
+        });
+        let _ = window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(cleanup.unchecked_ref(), time);
+    }
+
+    fn targets(origin: &Element, to: &Option<String>) -> Vec<Element> {
+        let Some(selector) = to else {
+            return vec![origin.clone()];
+        };
+
+        let Some(nodes) = origin
+            .owner_document()
+            .and_then(|document| document.query_selector_all(selector).ok())
+        else {
+            return Vec::new();
+        };
+
+        (0..nodes.length())
+            .filter_map(|i| nodes.get(i))
+            .filter_map(|node| node.dyn_into::<Element>().ok())
+            .collect()
+    }
+}
+
+// changes made by commands, replayed after the differ patches the same element
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LocalEdits {
+    added_classes: BTreeSet<String>,
+    removed_classes: BTreeSet<String>,
+    display: Option<String>,
+    attributes: BTreeMap<String, String>,
+}
+
+thread_local! {
+    static LOCAL_EDITS: js_sys::WeakMap = js_sys::WeakMap::new();
+}
+
+impl LocalEdits {
+    fn add_class(&mut self, name: &str) {
+        self.removed_classes.remove(name);
+        self.added_classes.insert(name.to_string());
+    }
+
+    fn remove_class(&mut self, name: &str) {
+        self.added_classes.remove(name);
+        self.removed_classes.insert(name.to_string());
+    }
+
+    fn load(element: &Element) -> Option<Self> {
+        let stored = LOCAL_EDITS.with(|edits| edits.get(element.as_ref()));
+        stored
+            .as_string()
+            .and_then(|json| serde_json::from_str(&json).ok())
+    }
+
+    fn update(element: &Element, change: impl FnOnce(&mut Self)) {
+        let mut edits = Self::load(element).unwrap_or_default();
+        change(&mut edits);
+
+        if let Ok(json) = serde_json::to_string(&edits) {
+            LOCAL_EDITS.with(|stored| {
+                stored.set(element.as_ref(), &json.into());
+            });
+        }
+        edits.apply(element);
+    }
+
+    pub fn reapply(element: &Element) {
+        if let Some(edits) = Self::load(element) {
+            edits.apply(element);
+        }
+    }
+
+    fn apply(&self, element: &Element) {
+        let class_list = element.class_list();
+        for name in &self.added_classes {
+            let _ = class_list.add_1(name);
+        }
+        for name in &self.removed_classes {
+            let _ = class_list.remove_1(name);
+        }
+
+        for (name, value) in &self.attributes {
+            let _ = element.set_attribute(name, value);
+        }
+
+        if let (Some(display), Some(el)) = (&self.display, element.dyn_ref::<HtmlElement>()) {
+            let _ = el.style().set_property("display", display);
+        }
+    }
+}
@@ -12,4 +12,18 @@ pub struct ElementContent {
     pub children: Vec<ElementContent>,
     #[serde(default)]
     pub key: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl ElementContent {
+    pub fn inherit_scope(&mut self, inherited: Option<&str>) {
+        if self.scope.is_none() {
+            self.scope = inherited.map(str::to_string);
+        }
+        let scope = self.scope.clone();
+        for child in &mut self.children {
+            child.inherit_scope(scope.as_deref());
+        }
+    }
 }
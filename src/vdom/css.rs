@@ -0,0 +1,260 @@
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+pub fn scope_attribute(scope: &str) -> String {
+    let scope: String = scope
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    format!("data-q-s{}", scope)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CssProperties {
+    List(Vec<(String, String)>),
+    Map(IndexMap<String, String>),
+}
+
+impl CssProperties {
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        match self {
+            Self::List(list) => Box::new(list.iter().map(|(p, v)| (p.as_str(), v.as_str()))),
+            Self::Map(map) => Box::new(map.iter().map(|(p, v)| (p.as_str(), v.as_str()))),
+        }
+    }
+}
+
+// variant order matters: serde tries them top to bottom
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CssRule {
+    Scoped {
+        scope: String,
+        rules: Vec<CssRule>,
+    },
+    Block {
+        at: String,
+        rules: Vec<CssRule>,
+    },
+    AtProperties {
+        at: String,
+        properties: CssProperties,
+    },
+    Statement {
+        at: String,
+    },
+    Style {
+        selector: String,
+        properties: CssProperties,
+    },
+}
+
+impl CssRule {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Scoped { scope, .. } => scope,
+            Self::Block { at, .. } | Self::AtProperties { at, .. } | Self::Statement { at } => at,
+            Self::Style { selector, .. } => selector,
+        }
+    }
+
+    pub fn to_css(&self) -> String {
+        self.format_at_depth(0)
+    }
+
+    fn format_at_depth(&self, depth: usize) -> String {
+        let indent = "  ".repeat(depth);
+        match self {
+            Self::Scoped { scope, rules } => rules
+                .iter()
+                .map(|rule| rule.with_scope(scope).format_at_depth(depth))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Block { at, rules } => {
+                let body = rules
+                    .iter()
+                    .map(|rule| rule.format_at_depth(depth + 1))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{}{} {{\n{}\n{}}}", indent, at, body, indent)
+            }
+            Self::AtProperties { at, properties } => {
+                Self::format_properties(&indent, at, properties)
+            }
+            Self::Statement { at } => format!("{}{};", indent, at.trim_end_matches(';')),
+            Self::Style {
+                selector,
+                properties,
+            } => Self::format_properties(&indent, selector, properties),
+        }
+    }
+
+    // a scoped group is several top-level rules, and the CSSOM inserts them one at a time
+    pub fn flatten(&self) -> Vec<CssRule> {
+        match self {
+            Self::Scoped { scope, rules } => rules
+                .iter()
+                .flat_map(|rule| rule.with_scope(scope).flatten())
+                .collect(),
+            _ => vec![self.clone()],
+        }
+    }
+
+    pub fn with_scope(&self, scope: &str) -> CssRule {
+        match self {
+            // the innermost scope wins for nested components
+            Self::Scoped { .. } => self.clone(),
+            // keyframe selectors such as `from` or `50%` address the animation, not elements
+            Self::Block { at, .. } if at.trim_start().starts_with("@keyframes") => self.clone(),
+            Self::Block { at, rules } => Self::Block {
+                at: at.clone(),
+                rules: rules.iter().map(|rule| rule.with_scope(scope)).collect(),
+            },
+            Self::Style {
+                selector,
+                properties,
+            } => Self::Style {
+                selector: Self::scope_selector(selector, &scope_attribute(scope)),
+                properties: properties.clone(),
+            },
+            Self::AtProperties { .. } | Self::Statement { .. } => self.clone(),
+        }
+    }
+
+    fn scope_selector(selector: &str, attribute: &str) -> String {
+        Self::split_top_level(selector, ',')
+            .into_iter()
+            .map(|part| {
+                let part = part.trim();
+                // pseudo-elements have to stay last in a compound selector
+                match Self::find_top_level(part, "::") {
+                    Some(index) => format!("{}[{}]{}", &part[..index], attribute, &part[index..]),
+                    None => format!("{}[{}]", part, attribute),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn split_top_level(selector: &str, separator: char) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let mut depth = 0i32;
+        let mut start = 0;
+        for (index, ch) in selector.char_indices() {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                ch if ch == separator && depth == 0 => {
+                    parts.push(&selector[start..index]);
+                    start = index + ch.len_utf8();
+                }
+                _ => {}
+            }
+        }
+        parts.push(&selector[start..]);
+        parts
+    }
+
+    fn find_top_level(selector: &str, needle: &str) -> Option<usize> {
+        let mut depth = 0i32;
+        for (index, ch) in selector.char_indices() {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                _ if depth == 0 && selector[index..].starts_with(needle) => return Some(index),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn format_properties(indent: &str, prelude: &str, properties: &CssProperties) -> String {
+        let props = properties
+            .iter()
+            .map(|(prop, value)| format!("{}  {}: {};", indent, prop, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{}{} {{\n{}\n{}}}", indent, prelude, props, indent)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CssRules {
+    Ordered(Vec<CssRule>),
+    // the original `selector -> properties` object, now read in document order
+    Legacy(IndexMap<String, IndexMap<String, String>>),
+}
+
+impl CssRules {
+    pub fn to_rules(&self) -> Vec<CssRule> {
+        match self {
+            Self::Ordered(rules) => rules.iter().flat_map(CssRule::flatten).collect(),
+            Self::Legacy(map) => map
+                .iter()
+                .map(|(selector, properties)| CssRule::Style {
+                    selector: selector.clone(),
+                    properties: CssProperties::Map(properties.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(selector: &str) -> CssRule {
+        CssRule::Style {
+            selector: selector.to_string(),
+            properties: CssProperties::List(vec![("color".to_string(), "red".to_string())]),
+        }
+    }
+
+    #[test]
+    fn scopes_each_selector_before_pseudo_elements() {
+        let scoped = style("a, p::before").with_scope("x1");
+        assert_eq!(scoped, style("a[data-q-sx1], p[data-q-sx1]::before"));
+    }
+
+    #[test]
+    fn leaves_keyframes_unscoped() {
+        let keyframes = CssRule::Block {
+            at: "@keyframes spin".to_string(),
+            rules: vec![style("from")],
+        };
+        assert_eq!(keyframes.with_scope("x1"), keyframes);
+    }
+
+    #[test]
+    fn flattens_scoped_groups_into_single_rules() {
+        let rules = CssRules::Ordered(vec![CssRule::Scoped {
+            scope: "x1".to_string(),
+            rules: vec![
+                style("a"),
+                CssRule::Scoped {
+                    scope: "x2".to_string(),
+                    rules: vec![style("b")],
+                },
+                CssRule::Block {
+                    at: "@media print".to_string(),
+                    rules: vec![style("c")],
+                },
+            ],
+        }]);
+
+        assert_eq!(
+            rules.to_rules(),
+            vec![
+                style("a[data-q-sx1]"),
+                style("b[data-q-sx2]"),
+                CssRule::Block {
+                    at: "@media print".to_string(),
+                    rules: vec![style("c[data-q-sx1]")],
+                },
+            ]
+        );
+    }
+}
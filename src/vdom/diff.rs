@@ -1,9 +1,10 @@
 use super::core::ElementContent;
 use super::patch::Patcher;
 use super::render::DomRenderer;
+use crate::connection::Socket;
 use std::collections::{HashMap, HashSet};
 use wasm_bindgen::{JsCast, JsValue};
-use web_sys::{Document, Element, WebSocket};
+use web_sys::{Document, Element};
 
 pub struct Differ<'a> {
     renderer: &'a DomRenderer,
@@ -18,7 +19,7 @@ impl<'a> Differ<'a> {
     pub fn diff_and_patch(
         &self,
         document: &Document,
-        ws: &WebSocket,
+        ws: &Socket,
         parent_dom: &Element,
         current_dom_node: Option<&Element>,
         old_vnode: Option<&ElementContent>,
@@ -43,6 +44,11 @@ impl<'a> Differ<'a> {
                         &new_vnode.attributes,
                         ws,
                     )?;
+                    self.patcher.patch_scope(
+                        current_d,
+                        old_v.scope.as_deref(),
+                        new_vnode.scope.as_deref(),
+                    )?;
                     if old_v.text != new_vnode.text {
                         current_d.set_text_content(new_vnode.text.as_deref());
                     }
@@ -62,7 +68,7 @@ impl<'a> Differ<'a> {
     fn reconcile_children(
         &self,
         document: &Document,
-        ws: &WebSocket,
+        ws: &Socket,
         parent_dom: &Element,
         old_children: &[ElementContent],
         new_children: &[ElementContent],
@@ -80,31 +86,30 @@ impl<'a> Differ<'a> {
         let child_nodes = parent_dom.child_nodes();
         let mut dom_children = Vec::new();
         for i in 0..child_nodes.length() {
-            if let Some(node) = child_nodes.item(i) {
-                if let Ok(element) = node.dyn_into::<Element>() {
-                    dom_children.push(element);
-                }
+            if let Some(node) = child_nodes.item(i)
+                && let Ok(element) = node.dyn_into::<Element>()
+            {
+                dom_children.push(element);
             }
         }
 
         for new_child in new_children {
             let mut found_match = false;
-            if let Some(key) = &new_child.key {
-                if let Some((old_idx, old_child)) = old_keyed_map.get(key) {
-                    if let Some(dom_el) = dom_children.get(*old_idx) {
-                        self.diff_and_patch(
-                            document,
-                            ws,
-                            parent_dom,
-                            Some(dom_el),
-                            Some(old_child),
-                            new_child,
-                        )?;
-                        new_dom_children.push(dom_el.clone());
-                        used_old_indices.insert(*old_idx);
-                        found_match = true;
-                    }
-                }
+            if let Some(key) = &new_child.key
+                && let Some((old_idx, old_child)) = old_keyed_map.get(key)
+                && let Some(dom_el) = dom_children.get(*old_idx)
+            {
+                self.diff_and_patch(
+                    document,
+                    ws,
+                    parent_dom,
+                    Some(dom_el),
+                    Some(old_child),
+                    new_child,
+                )?;
+                new_dom_children.push(dom_el.clone());
+                used_old_indices.insert(*old_idx);
+                found_match = true;
             }
             if !found_match {
                 let new_el = self.renderer.create_dom_element(document, ws, new_child)?;
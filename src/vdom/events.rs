@@ -0,0 +1,73 @@
+use wasm_bindgen::JsCast;
+use web_sys::{AddEventListenerOptions, Element, Event};
+
+// prevent and stop are read from the element on every event, so patches take effect at once;
+// once and passive are listener options fixed when the listener is attached, and changing
+// them on an element that already has its listener has no effect
+#[derive(Debug, Clone, Default)]
+pub struct EventOptions {
+    pub prevent: Option<bool>,
+    pub stop: bool,
+    pub once: bool,
+    pub passive: bool,
+}
+
+impl EventOptions {
+    const PREVENT_ATTR: &'static str = "data-prevent";
+    const STOP_ATTR: &'static str = "data-stop";
+    const ONCE_ATTR: &'static str = "data-once";
+    const PASSIVE_ATTR: &'static str = "data-passive";
+    const PASSIVE_EVENTS: [&'static str; 4] = ["scroll", "wheel", "touchstart", "touchmove"];
+
+    pub fn from_element(element: &Element, event_type: &str) -> Self {
+        let flag = |attr: &str| {
+            element
+                .get_attribute(attr)
+                .map(|value| Self::matches_event(&value, event_type))
+        };
+
+        Self {
+            prevent: flag(Self::PREVENT_ATTR),
+            stop: flag(Self::STOP_ATTR).unwrap_or(false),
+            once: flag(Self::ONCE_ATTR).unwrap_or(false),
+            passive: flag(Self::PASSIVE_ATTR)
+                .unwrap_or_else(|| Self::PASSIVE_EVENTS.contains(&event_type)),
+        }
+    }
+
+    pub fn from_event(event: &Event, event_type: &str) -> Self {
+        event
+            .current_target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .map(|element| Self::from_element(&element, event_type))
+            .unwrap_or_default()
+    }
+
+    // "" and "true" cover every event, "false" none, anything else is a list of event types
+    fn matches_event(value: &str, event_type: &str) -> bool {
+        match value.trim() {
+            "" | "true" => true,
+            "false" => false,
+            list => list.split(',').any(|name| name.trim() == event_type),
+        }
+    }
+
+    // only consulted when the listener is attached
+    pub fn listener_options(&self) -> AddEventListenerOptions {
+        let options = AddEventListenerOptions::new();
+        options.set_once(self.once);
+        options.set_passive(self.passive);
+        options
+    }
+
+    pub fn apply(&self, event: &Event, prevent_by_default: bool) -> bool {
+        let prevent = !self.passive && self.prevent.unwrap_or(prevent_by_default);
+        if prevent {
+            event.prevent_default();
+        }
+        if self.stop {
+            event.stop_propagation();
+        }
+        prevent
+    }
+}
@@ -0,0 +1,212 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Event, KeyboardEvent};
+
+use crate::utils::log;
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeyCombo {
+    key: String,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    meta: bool,
+}
+
+impl KeyCombo {
+    fn parse(token: &str) -> Option<Self> {
+        let token = token.trim();
+        let (modifiers, key) = if token == "+" {
+            ("", "+")
+        } else if let Some(modifiers) = token.strip_suffix("++") {
+            (modifiers, "+")
+        } else {
+            token.rsplit_once('+').unwrap_or(("", token))
+        };
+
+        if key.is_empty() {
+            return None;
+        }
+
+        let mut combo = Self {
+            key: Self::normalize_key(key),
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+        };
+
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "control" => combo.ctrl = true,
+                "alt" | "option" => combo.alt = true,
+                "shift" => combo.shift = true,
+                "meta" | "cmd" | "super" => combo.meta = true,
+                _ => return None,
+            }
+        }
+
+        Some(combo)
+    }
+
+    fn normalize_key(key: &str) -> String {
+        match key.to_lowercase().as_str() {
+            "space" => " ".to_string(),
+            "esc" => "escape".to_string(),
+            "up" | "down" | "left" | "right" => format!("arrow{}", key.to_lowercase()),
+            other => other.to_string(),
+        }
+    }
+
+    fn matches(&self, event: &KeyboardEvent) -> bool {
+        self.matches_parts(
+            &event.key(),
+            &event.code(),
+            event.ctrl_key(),
+            event.alt_key(),
+            event.shift_key(),
+            event.meta_key(),
+        )
+    }
+
+    fn matches_parts(
+        &self,
+        key: &str,
+        code: &str,
+        ctrl: bool,
+        alt: bool,
+        shift: bool,
+        meta: bool,
+    ) -> bool {
+        if key.to_lowercase() != self.key && code.to_lowercase() != self.key {
+            return false;
+        }
+
+        // a bare printable character already carries the shift state in its value; once any
+        // modifier is named the combo is exact, so ctrl+s does not fire on ctrl+shift+s
+        let exact = self.ctrl || self.alt || self.shift || self.meta;
+        let shift_matches = if exact || self.key.chars().count() != 1 {
+            shift == self.shift
+        } else {
+            true
+        };
+
+        shift_matches && ctrl == self.ctrl && alt == self.alt && meta == self.meta
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    combos: Vec<KeyCombo>,
+}
+
+impl KeyFilter {
+    const KEYS_ATTR: &'static str = "data-keys";
+
+    // also returns the tokens that are not valid combos
+    pub fn parse(value: &str) -> (Self, Vec<&str>) {
+        let mut combos = Vec::new();
+        let mut invalid = Vec::new();
+        for token in value.split(',').filter(|token| !token.trim().is_empty()) {
+            match KeyCombo::parse(token) {
+                Some(combo) => combos.push(combo),
+                None => invalid.push(token.trim()),
+            }
+        }
+        (Self { combos }, invalid)
+    }
+
+    // an empty filter, or one with no valid combo, is no filter rather than one that blocks
+    // every key
+    pub fn from_element(element: &Element) -> Option<Self> {
+        let value = element.get_attribute(Self::KEYS_ATTR)?;
+        let (filter, invalid) = Self::parse(&value);
+        if !invalid.is_empty() {
+            log(&format!(
+                "Ignoring invalid {} tokens: {}",
+                Self::KEYS_ATTR,
+                invalid.join(", ")
+            ));
+        }
+        (!filter.combos.is_empty()).then_some(filter)
+    }
+
+    pub fn matches(&self, event: &KeyboardEvent) -> bool {
+        self.combos.iter().any(|combo| combo.matches(event))
+    }
+
+    pub fn allows(event: &Event) -> bool {
+        let Ok(keyboard_event) = event.clone().dyn_into::<KeyboardEvent>() else {
+            return true;
+        };
+
+        event
+            .current_target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|element| Self::from_element(&element))
+            .is_none_or(|filter| filter.matches(&keyboard_event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(token: &str) -> KeyCombo {
+        KeyCombo::parse(token).expect("valid combo")
+    }
+
+    #[test]
+    fn parses_modifiers_and_aliases() {
+        let parsed = combo("Ctrl+Shift+Up");
+        assert_eq!(parsed.key, "arrowup");
+        assert!(parsed.ctrl && parsed.shift && !parsed.alt && !parsed.meta);
+        assert_eq!(combo("esc").key, "escape");
+        assert_eq!(combo("space").key, " ");
+    }
+
+    #[test]
+    fn parses_plus_as_a_key() {
+        assert_eq!(combo("+").key, "+");
+        let parsed = combo("ctrl++");
+        assert_eq!(parsed.key, "+");
+        assert!(parsed.ctrl);
+    }
+
+    #[test]
+    fn rejects_unknown_modifiers() {
+        assert_eq!(KeyCombo::parse("hyper+a"), None);
+        assert_eq!(KeyCombo::parse("ctrl+"), None);
+    }
+
+    #[test]
+    fn bare_character_ignores_shift() {
+        let parsed = combo("a");
+        assert!(parsed.matches_parts("A", "KeyA", false, false, true, false));
+        assert!(parsed.matches_parts("a", "KeyA", false, false, false, false));
+    }
+
+    #[test]
+    fn modifiers_compare_shift_strictly() {
+        let parsed = combo("ctrl+s");
+        assert!(parsed.matches_parts("s", "KeyS", true, false, false, false));
+        assert!(!parsed.matches_parts("S", "KeyS", true, false, true, false));
+    }
+
+    #[test]
+    fn named_keys_compare_shift_strictly() {
+        let parsed = combo("enter");
+        assert!(parsed.matches_parts("Enter", "Enter", false, false, false, false));
+        assert!(!parsed.matches_parts("Enter", "Enter", false, false, true, false));
+    }
+
+    #[test]
+    fn filter_reports_invalid_tokens() {
+        let (filter, invalid) = KeyFilter::parse("enter, hyper+x, ,ctrl+k");
+        assert_eq!(filter.combos.len(), 2);
+        assert_eq!(invalid, vec!["hyper+x"]);
+
+        let (filter, invalid) = KeyFilter::parse("");
+        assert!(filter.combos.is_empty());
+        assert!(invalid.is_empty());
+    }
+}
@@ -1,49 +1,85 @@
+mod commands;
 mod core;
+mod css;
 mod diff;
+mod events;
+mod keys;
 mod patch;
+mod pending;
 mod render;
+mod sanitize;
+mod styles;
 
 pub use self::core::ElementContent;
+pub use self::css::CssRules;
 use self::diff::Differ;
 use self::patch::Patcher;
+use self::pending::PendingRequests;
 use self::render::DomRenderer;
+use self::sanitize::CssSanitizer;
+use self::styles::StyleSheetState;
 
-use crate::connection::Crypto;
+use crate::connection::{ClientMessage, Crypto, Messaging, Socket, Uploads};
 use crate::utils::log;
 use once_cell::sync::Lazy;
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Mutex;
-use web_sys::{WebSocket, Window};
+use web_sys::Window;
 
 pub struct VirtualDom {
     previous_vdom: Lazy<Mutex<Option<ElementContent>>>,
     style_tag_id: &'static str,
+    stylesheet: RefCell<StyleSheetState>,
     crypto: Rc<RefCell<Crypto>>,
+    uploads: Rc<RefCell<Uploads>>,
+    pending: Rc<RefCell<PendingRequests>>,
 }
 
 impl VirtualDom {
-    pub fn new(crypto: Rc<RefCell<Crypto>>) -> Self {
+    pub fn new(crypto: Rc<RefCell<Crypto>>, uploads: Rc<RefCell<Uploads>>) -> Self {
         VirtualDom {
             previous_vdom: Lazy::new(|| Mutex::new(None)),
             style_tag_id: "quillion-dynamic-styles",
+            stylesheet: RefCell::new(StyleSheetState::new()),
             crypto,
+            uploads,
+            pending: Rc::new(RefCell::new(PendingRequests::new())),
         }
     }
 
+    pub fn settle_request(&self, request_id: u64) {
+        self.pending.borrow_mut().settle(request_id);
+    }
+
     pub fn render_page(
         &self,
         window: &Window,
-        ws: &WebSocket,
+        ws: &Socket,
         new_content: &[ElementContent],
         path_opt: &Option<String>,
-        css_rules_opt: &Option<HashMap<String, HashMap<String, String>>>,
+        css_rules_opt: &Option<CssRules>,
     ) {
+        // scopes are resolved up front so both vdom snapshots carry them on every node
+        let new_content: Vec<ElementContent> = new_content
+            .iter()
+            .cloned()
+            .map(|mut content| {
+                content.inherit_scope(None);
+                content
+            })
+            .collect();
+        let new_content = new_content.as_slice();
+        self.pending.borrow_mut().settle_all();
+
         let document = window.document().expect("Document should exist");
         let body = document.body().expect("Document body should exist");
 
-        let renderer = DomRenderer::new(self.crypto.clone());
+        let renderer = DomRenderer::new(
+            self.crypto.clone(),
+            self.uploads.clone(),
+            self.pending.clone(),
+        );
         let patcher = Patcher::new(&renderer);
         let differ = Differ::new(&renderer, &patcher);
 
@@ -59,9 +95,9 @@ impl VirtualDom {
                     let _ = body.append_child(&el);
                 }
             }
-            *prev_vdom_guard = new_content.get(0).cloned();
+            *prev_vdom_guard = new_content.first().cloned();
         } else {
-            if let (Some(old_root), Some(new_root)) = (old_vdom_root, new_content.get(0)) {
+            if let (Some(old_root), Some(new_root)) = (old_vdom_root, new_content.first()) {
                 let current_root_dom = body.first_element_child();
                 if let Err(e) = differ.diff_and_patch(
                     &document,
@@ -78,7 +114,32 @@ impl VirtualDom {
         }
 
         if let Some(css_rules) = css_rules_opt {
-            let _ = renderer.apply_css_rules(&document, self.style_tag_id, css_rules);
+            let mut rejected = Vec::new();
+            let rules: Vec<_> = css_rules
+                .to_rules()
+                .iter()
+                .filter_map(|rule| CssSanitizer::rule(rule, &mut rejected))
+                .collect();
+
+            for error in rejected {
+                log(&error.to_string());
+                Messaging::send_encrypted_message(
+                    ws,
+                    &ClientMessage::ClientError {
+                        error: error.to_string(),
+                    },
+                    &self.crypto.borrow(),
+                );
+            }
+
+            if let Err(e) = renderer.apply_css_rules(
+                &document,
+                self.style_tag_id,
+                &rules,
+                &mut self.stylesheet.borrow_mut(),
+            ) {
+                log(&format!("Cannot apply CSS rules: {:?}", e));
+            }
         }
 
         if let Some(path) = path_opt {
@@ -1,7 +1,10 @@
+use super::commands::LocalEdits;
+use super::css::scope_attribute;
 use super::render::DomRenderer;
+use crate::connection::Socket;
 use std::collections::HashMap;
 use wasm_bindgen::JsValue;
-use web_sys::{Element, WebSocket};
+use web_sys::Element;
 
 pub struct Patcher<'a> {
     renderer: &'a DomRenderer,
@@ -12,14 +15,32 @@ impl<'a> Patcher<'a> {
         Self { renderer }
     }
 
+    pub fn patch_scope(
+        &self,
+        element: &Element,
+        old_scope: Option<&str>,
+        new_scope: Option<&str>,
+    ) -> Result<(), JsValue> {
+        if old_scope == new_scope {
+            return Ok(());
+        }
+        if let Some(scope) = old_scope {
+            element.remove_attribute(&scope_attribute(scope))?;
+        }
+        if let Some(scope) = new_scope {
+            element.set_attribute(&scope_attribute(scope), "")?;
+        }
+        Ok(())
+    }
+
     pub fn patch_attributes(
         &self,
         element: &Element,
         old_attrs: &HashMap<String, String>,
         new_attrs: &HashMap<String, String>,
-        ws: &WebSocket,
+        ws: &Socket,
     ) -> Result<(), JsValue> {
-        for (key, _) in old_attrs {
+        for key in old_attrs.keys() {
             if !new_attrs.contains_key(key) {
                 element.remove_attribute(key)?;
             }
@@ -34,6 +55,8 @@ impl<'a> Patcher<'a> {
                 )?;
             }
         }
+
+        LocalEdits::reapply(element);
         Ok(())
     }
 }
@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::Element;
+
+struct PendingMark {
+    element: Element,
+    label: Option<(Element, Option<String>)>,
+}
+
+// ids come from the connection, so they match the request ids the server echoes
+#[derive(Default)]
+pub struct PendingRequests {
+    marks: HashMap<u64, PendingMark>,
+}
+
+impl PendingRequests {
+    // opts a control in; only controls carrying it or data-disable-with are ever locked
+    const PENDING_ATTR: &'static str = "data-pending";
+    const REQUEST_ATTR: &'static str = "data-pending-request";
+    const BUSY_ATTR: &'static str = "aria-busy";
+    const DISABLE_WITH_ATTR: &'static str = "data-disable-with";
+    // servers that never echo a request id must not leave controls locked forever
+    const SETTLE_TIMEOUT_MS: i32 = 15_000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn opts_in(element: &Element, label_target: Option<&Element>) -> bool {
+        element.has_attribute(Self::PENDING_ATTR)
+            || element.has_attribute(Self::DISABLE_WITH_ATTR)
+            || label_target.is_some_and(|target| target.has_attribute(Self::DISABLE_WITH_ATTR))
+    }
+
+    pub fn is_pending(element: &Element) -> bool {
+        element.has_attribute(Self::REQUEST_ATTR)
+    }
+
+    pub fn begin(
+        pending: &Rc<RefCell<Self>>,
+        request_id: u64,
+        element: &Element,
+        label_target: Option<&Element>,
+    ) {
+        let mut this = pending.borrow_mut();
+        let _ = element.set_attribute(Self::REQUEST_ATTR, &request_id.to_string());
+        let _ = element.set_attribute(Self::BUSY_ATTR, "true");
+
+        let label = label_target
+            .filter(|target| target.has_attribute(Self::DISABLE_WITH_ATTR))
+            .map(|target| {
+                let original = target.text_content();
+                if let Some(busy_label) = target.get_attribute(Self::DISABLE_WITH_ATTR) {
+                    target.set_text_content(Some(&busy_label));
+                }
+                let _ = target.set_attribute("disabled", "");
+                (target.clone(), original)
+            });
+
+        this.marks.insert(
+            request_id,
+            PendingMark {
+                element: element.clone(),
+                label,
+            },
+        );
+        drop(this);
+
+        if let Some(window) = web_sys::window() {
+            let pending = pending.clone();
+            let settle = Closure::once_into_js(move || pending.borrow_mut().settle(request_id));
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                settle.unchecked_ref(),
+                Self::SETTLE_TIMEOUT_MS,
+            );
+        }
+    }
+
+    pub fn settle(&mut self, request_id: u64) {
+        let Some(mark) = self.marks.remove(&request_id) else {
+            return;
+        };
+
+        let _ = mark.element.remove_attribute(Self::REQUEST_ATTR);
+        let _ = mark.element.remove_attribute(Self::BUSY_ATTR);
+
+        if let Some((target, original)) = mark.label {
+            // a re-render may already have replaced the busy label with fresh content
+            if target.text_content() == target.get_attribute(Self::DISABLE_WITH_ATTR) {
+                target.set_text_content(original.as_deref());
+            }
+            let _ = target.remove_attribute("disabled");
+        }
+    }
+
+    // a rendered page supersedes whatever the marked controls were waiting for
+    pub fn settle_all(&mut self) {
+        let request_ids: Vec<u64> = self.marks.keys().copied().collect();
+        for request_id in request_ids {
+            self.settle(request_id);
+        }
+    }
+}
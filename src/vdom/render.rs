@@ -1,29 +1,51 @@
+use super::commands::JsCommand;
 use super::core::ElementContent;
-use crate::connection::{ClientMessage, Crypto, Messaging};
-use crate::utils::EventDataExtractor;
+use super::css::{CssRule, scope_attribute};
+use super::events::EventOptions;
+use super::keys::KeyFilter;
+use super::pending::PendingRequests;
+use super::styles::StyleSheetState;
+use crate::connection::{ClientMessage, Crypto, Messaging, Socket, Uploads};
+use crate::utils::{EventDataExtractor, FormValidator, log};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use wasm_bindgen::{JsCast, JsValue, closure::Closure};
-use web_sys::{Document, Element, WebSocket, Window};
+use web_sys::{Document, Element, Window};
 
 pub struct DomRenderer {
     crypto: Rc<RefCell<Crypto>>,
+    uploads: Rc<RefCell<Uploads>>,
+    pending: Rc<RefCell<PendingRequests>>,
 }
 
 impl DomRenderer {
-    pub fn new(crypto: Rc<RefCell<Crypto>>) -> Self {
-        Self { crypto }
+    // a property on the element object rather than an attribute, so patches cannot clear it
+    const BOUND_PROPERTY: &'static str = "__quillionBound";
+
+    pub fn new(
+        crypto: Rc<RefCell<Crypto>>,
+        uploads: Rc<RefCell<Uploads>>,
+        pending: Rc<RefCell<PendingRequests>>,
+    ) -> Self {
+        Self {
+            crypto,
+            uploads,
+            pending,
+        }
     }
 
     pub fn create_dom_element(
         &self,
         document: &Document,
-        ws: &WebSocket,
+        ws: &Socket,
         content: &ElementContent,
     ) -> Result<Element, JsValue> {
         let el = document.create_element(&content.tag)?;
         self.apply_attributes(ws, &el, &content.attributes)?;
+        if let Some(scope) = &content.scope {
+            el.set_attribute(&scope_attribute(scope), "")?;
+        }
 
         if let Some(text) = &content.text {
             el.set_text_content(Some(text));
@@ -39,32 +61,124 @@ impl DomRenderer {
 
     pub fn apply_attributes(
         &self,
-        ws: &WebSocket,
+        ws: &Socket,
         element: &Element,
         attributes: &HashMap<String, String>,
     ) -> Result<(), JsValue> {
-        for (key, value) in attributes {
+        let (handlers, plain): (Vec<_>, Vec<_>) = attributes
+            .iter()
+            .partition(|(key, _)| Self::is_handler_attribute(element, key));
+
+        // event options are read back from the element, so they must land before any listener
+        for (key, value) in plain {
+            element.set_attribute(key, value)?;
+        }
+
+        for (key, value) in handlers {
             match key.as_str() {
-                key if key.starts_with("on") && key.len() > 2 => {
-                    self.set_event_handler(ws, element, key, value)?;
-                }
                 "data-callback-id" => {
                     self.set_callback_handler(ws, element, value)?;
                 }
-                "href" if element.tag_name().to_lowercase() == "a" => {
+                "href" => {
                     self.set_link_handler(ws, element, value)?;
                 }
+                Uploads::MODE_ATTR => {
+                    self.set_upload_handler(ws, element, value)?;
+                }
+                key if key.starts_with(JsCommand::ATTR_PREFIX) => {
+                    self.set_command_handler(ws, element, key, value)?;
+                }
                 _ => {
-                    element.set_attribute(key, value)?;
+                    self.set_event_handler(ws, element, key, value)?;
                 }
             }
         }
         Ok(())
     }
 
+    fn is_handler_attribute(element: &Element, key: &str) -> bool {
+        match key {
+            "data-callback-id" => true,
+            "href" => element.tag_name().to_lowercase() == "a",
+            Uploads::MODE_ATTR => element.dyn_ref::<web_sys::HtmlInputElement>().is_some(),
+            key if key.starts_with(JsCommand::ATTR_PREFIX) => {
+                key.len() > JsCommand::ATTR_PREFIX.len()
+            }
+            key => key.starts_with("on") && key.len() > 2,
+        }
+    }
+
+    fn add_listener(
+        element: &Element,
+        event_type: &str,
+        closure: Closure<dyn FnMut(web_sys::Event)>,
+    ) -> Result<(), JsValue> {
+        let options = EventOptions::from_element(element, event_type);
+        Self::attach_listener(element, event_type, closure, &options)
+    }
+
+    // the browser drops a once listener after its first event, even one the key filter turns
+    // away, so a filtered listener spends its one event itself
+    fn add_keyed_listener(
+        element: &Element,
+        event_type: &str,
+        mut handler: impl FnMut(web_sys::Event) + 'static,
+    ) -> Result<(), JsValue> {
+        let options = EventOptions::from_element(element, event_type);
+        let once = options.once;
+        let mut spent = false;
+
+        let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
+            if spent || !KeyFilter::allows(&event) {
+                return;
+            }
+            spent = once;
+            handler(event);
+        });
+
+        let options = EventOptions {
+            once: false,
+            ..options
+        };
+        Self::attach_listener(element, event_type, closure, &options)
+    }
+
+    // true the first time a listener is bound under this key on this element
+    fn claim_binding(element: &Element, key: &str) -> bool {
+        let property = JsValue::from_str(Self::BOUND_PROPERTY);
+        let bound = js_sys::Reflect::get(element, &property)
+            .ok()
+            .filter(|bound| bound.is_object())
+            .unwrap_or_else(|| {
+                let bound = js_sys::Object::new().into();
+                let _ = js_sys::Reflect::set(element, &property, &bound);
+                bound
+            });
+        let key = JsValue::from_str(key);
+        if js_sys::Reflect::has(&bound, &key).unwrap_or(false) {
+            return false;
+        }
+        js_sys::Reflect::set(&bound, &key, &JsValue::TRUE).is_ok()
+    }
+
+    fn attach_listener(
+        element: &Element,
+        event_type: &str,
+        closure: Closure<dyn FnMut(web_sys::Event)>,
+        options: &EventOptions,
+    ) -> Result<(), JsValue> {
+        element.add_event_listener_with_callback_and_add_event_listener_options(
+            event_type,
+            closure.as_ref().unchecked_ref(),
+            &options.listener_options(),
+        )?;
+        closure.forget();
+        Ok(())
+    }
+
     fn set_event_handler(
         &self,
-        ws: &WebSocket,
+        ws: &Socket,
         element: &Element,
         event_name: &str,
         callback_id: &str,
@@ -74,11 +188,36 @@ impl DomRenderer {
         let callback_id = callback_id.to_string();
         let ws_clone = ws.clone();
         let crypto_clone = self.crypto.clone();
+        let uploads_clone = self.uploads.clone();
+        let pending_clone = self.pending.clone();
 
-        let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
-            Self::handle_event(&event, &event_type_clone);
+        let handler = move |event: web_sys::Event| {
+            EventOptions::from_event(&event, &event_type_clone)
+                .apply(&event, Self::prevents_by_default(&event, &event_type_clone));
+
+            let submitted_form = event
+                .target()
+                .and_then(|target| target.dyn_into::<web_sys::HtmlFormElement>().ok())
+                .filter(|_| event_type_clone == "submit");
+
+            if let Some(form) = &submitted_form
+                && FormValidator::blocks_invalid(form)
+                && !form.check_validity()
+            {
+                form.report_validity();
+                return;
+            }
+
+            let Some(request_id) =
+                Self::track_request(&pending_clone, &ws_clone, &event, &event_type_clone)
+            else {
+                return;
+            };
 
             let event_data = Self::extract_event_data(&event, &event_type_clone);
+            let validity = submitted_form
+                .as_ref()
+                .map(|form| EventDataExtractor::extract_form_validity(form).to_string());
             let crypto = crypto_clone.borrow();
 
             Messaging::send_encrypted_message(
@@ -87,32 +226,62 @@ impl DomRenderer {
                     id: &callback_id,
                     event_type: event_type_clone.clone(),
                     event_data: event_data.unwrap_or_default(),
+                    validity,
+                    request_id: Some(request_id),
                 },
                 &crypto,
             );
-        });
 
-        element.add_event_listener_with_callback(&event_type, closure.as_ref().unchecked_ref())?;
-        closure.forget();
+            if let Some(form) = &submitted_form {
+                uploads_clone
+                    .borrow()
+                    .request_form(&ws_clone, &crypto, form);
+            }
+        };
 
-        Ok(())
+        Self::add_keyed_listener(element, &event_type, handler)
     }
 
-    fn handle_event(event: &web_sys::Event, event_type: &str) {
-        match event_type {
-            "submit" => {
-                event.prevent_default();
-            }
-            "click" => {
-                if let Some(target) = event.target() {
-                    if let Ok(element) = target.dyn_into::<Element>() {
-                        if element.tag_name().to_lowercase() == "a" {
-                            event.prevent_default();
-                        }
-                    }
+    // yields None while the same control is still waiting on an earlier request; controls are
+    // only locked when they opt in and the server echoes request ids
+    fn track_request(
+        pending: &Rc<RefCell<PendingRequests>>,
+        ws: &Socket,
+        event: &web_sys::Event,
+        event_type: &str,
+    ) -> Option<u64> {
+        let element = event
+            .current_target()
+            .and_then(|target| target.dyn_into::<Element>().ok());
+
+        match (event_type, element) {
+            ("click" | "submit", Some(element)) if ws.has_feature("request_ids") => {
+                if PendingRequests::is_pending(&element) {
+                    return None;
+                }
+                let submitter: Option<Element> = event
+                    .dyn_ref::<web_sys::SubmitEvent>()
+                    .and_then(|submit_event| submit_event.submitter())
+                    .map(Into::into);
+                let label_target = submitter.as_ref().unwrap_or(&element);
+                let request_id = ws.requests.next_id();
+                if PendingRequests::opts_in(&element, Some(label_target)) {
+                    PendingRequests::begin(pending, request_id, &element, Some(label_target));
                 }
+                Some(request_id)
             }
-            _ => {}
+            _ => Some(ws.requests.next_id()),
+        }
+    }
+
+    fn prevents_by_default(event: &web_sys::Event, event_type: &str) -> bool {
+        match event_type {
+            "submit" => true,
+            "click" => event
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .is_some_and(|element| element.tag_name().to_lowercase() == "a"),
+            _ => false,
         }
     }
 
@@ -125,10 +294,17 @@ impl DomRenderer {
             "click" | "dblclick" | "mousedown" | "mouseup" | "mousemove" => {
                 EventDataExtractor::extract_mouse_data(event)
             }
-            "submit" => event
-                .target()
-                .and_then(|target| target.dyn_into::<web_sys::HtmlFormElement>().ok())
-                .and_then(|form| EventDataExtractor::extract_form_data(&form)),
+            "submit" => {
+                let submitter = event
+                    .dyn_ref::<web_sys::SubmitEvent>()
+                    .and_then(|submit_event| submit_event.submitter());
+                event
+                    .target()
+                    .and_then(|target| target.dyn_into::<web_sys::HtmlFormElement>().ok())
+                    .and_then(|form| {
+                        EventDataExtractor::extract_form_data(&form, submitter.as_ref())
+                    })
+            }
             _ => None,
         };
         data.map(|d| d.to_string())
@@ -136,37 +312,38 @@ impl DomRenderer {
 
     fn set_callback_handler(
         &self,
-        ws: &WebSocket,
+        ws: &Socket,
         element: &Element,
         callback_id: &str,
     ) -> Result<(), JsValue> {
         let callback_id = callback_id.to_string();
         let ws_clone = ws.clone();
         let crypto_clone = self.crypto.clone();
+        let pending_clone = self.pending.clone();
 
         let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
-            event.prevent_default();
+            EventOptions::from_event(&event, "click").apply(&event, true);
+
+            let Some(request_id) = Self::track_request(&pending_clone, &ws_clone, &event, "click")
+            else {
+                return;
+            };
 
             let crypto = crypto_clone.borrow();
             Messaging::send_encrypted_message(
                 &ws_clone,
-                &ClientMessage::Callback { id: &callback_id },
+                &ClientMessage::Callback {
+                    id: &callback_id,
+                    request_id: Some(request_id),
+                },
                 &crypto,
             );
         });
 
-        element.add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())?;
-        closure.forget();
-
-        Ok(())
+        Self::add_listener(element, "click", closure)
     }
 
-    fn set_link_handler(
-        &self,
-        ws: &WebSocket,
-        element: &Element,
-        path: &str,
-    ) -> Result<(), JsValue> {
+    fn set_link_handler(&self, ws: &Socket, element: &Element, path: &str) -> Result<(), JsValue> {
         element.set_attribute("href", path)?;
 
         let path = path.to_string();
@@ -174,7 +351,10 @@ impl DomRenderer {
         let crypto_clone = self.crypto.clone();
 
         let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
-            event.prevent_default();
+            // a link that keeps its default action is left to the browser's own navigation
+            if !EventOptions::from_event(&event, "click").apply(&event, true) {
+                return;
+            }
 
             let crypto = crypto_clone.borrow();
             Messaging::send_encrypted_message(
@@ -184,46 +364,112 @@ impl DomRenderer {
             );
         });
 
-        element.add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())?;
-        closure.forget();
-
-        Ok(())
+        Self::add_listener(element, "click", closure)
     }
 
-    pub fn apply_css_rules(
+    fn set_command_handler(
         &self,
-        document: &Document,
-        style_tag_id: &str,
-        css_rules: &HashMap<String, HashMap<String, String>>,
+        ws: &Socket,
+        element: &Element,
+        attr_name: &str,
+        commands: &str,
     ) -> Result<(), JsValue> {
-        let head = document.head().ok_or("No <head> element found")?;
+        // commands are read from the attribute when the event fires, so patches only update it
+        element.set_attribute(attr_name, commands)?;
+        if !Self::claim_binding(element, attr_name) {
+            return Ok(());
+        }
 
-        if let Some(old_style) = document.get_element_by_id(style_tag_id) {
-            let _ = head.remove_child(&old_style);
+        let event_type = attr_name[JsCommand::ATTR_PREFIX.len()..].to_string();
+        let event_type_clone = event_type.clone();
+        let attr_name = attr_name.to_string();
+        let ws_clone = ws.clone();
+        let crypto_clone = self.crypto.clone();
+
+        let handler = move |event: web_sys::Event| {
+            let Some(element) = event
+                .current_target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+            else {
+                return;
+            };
+            let Some(commands) = element.get_attribute(&attr_name) else {
+                return;
+            };
+
+            EventOptions::from_event(&event, &event_type_clone).apply(&event, false);
+
+            match JsCommand::parse(&commands) {
+                Ok(commands) => JsCommand::execute_all(&commands, &element, &mut |callback_id| {
+                    Messaging::send_encrypted_message(
+                        &ws_clone,
+                        &ClientMessage::Callback {
+                            id: callback_id,
+                            request_id: None,
+                        },
+                        &crypto_clone.borrow(),
+                    );
+                }),
+                Err(e) => log(&format!("Invalid {} commands: {}", attr_name, e)),
+            }
+        };
+
+        Self::add_keyed_listener(element, &event_type, handler)
+    }
+
+    fn set_upload_handler(
+        &self,
+        ws: &Socket,
+        element: &Element,
+        mode: &str,
+    ) -> Result<(), JsValue> {
+        // the mode is read when files are picked, so a patch that changes it only updates it
+        element.set_attribute(Uploads::MODE_ATTR, mode)?;
+        if !Self::claim_binding(element, Uploads::MODE_ATTR) {
+            return Ok(());
         }
 
-        let style_tag = document
-            .create_element("style")?
-            .dyn_into::<web_sys::HtmlElement>()?;
-        style_tag.set_id(style_tag_id);
+        let ws_clone = ws.clone();
+        let crypto_clone = self.crypto.clone();
+        let uploads_clone = self.uploads.clone();
+
+        let closure = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
+            let Some(input) = event
+                .current_target()
+                .and_then(|target| target.dyn_into::<web_sys::HtmlInputElement>().ok())
+            else {
+                return;
+            };
+
+            // a patch may have taken the input out of uploading since the listener was bound
+            let Some(mode) = input.get_attribute(Uploads::MODE_ATTR) else {
+                return;
+            };
+
+            let mut uploads = uploads_clone.borrow_mut();
+            let upload_ids = uploads.select(&input);
+            // submit-mode files wait until their form is sent
+            if mode != "submit" {
+                uploads.request(&ws_clone, &crypto_clone.borrow(), &upload_ids);
+            }
+        });
+
+        Self::add_listener(element, "change", closure)
+    }
 
-        let css_string = css_rules
+    pub fn apply_css_rules(
+        &self,
+        document: &Document,
+        style_tag_id: &str,
+        css_rules: &[CssRule],
+        stylesheet: &mut StyleSheetState,
+    ) -> Result<(), JsValue> {
+        let target: Vec<(String, String)> = css_rules
             .iter()
-            .map(|(selector, properties)| {
-                let props = properties
-                    .iter()
-                    .map(|(prop, value)| format!("  {}: {};", prop, value))
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                format!("{} {{\n{}\n}}", selector, props)
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        style_tag.set_text_content(Some(&css_string));
-        head.append_child(&style_tag)?;
+            .map(|rule| (rule.key().to_string(), rule.to_css()))
+            .collect();
 
-        Ok(())
+        stylesheet.apply(document, style_tag_id, &target)
     }
 
     pub fn update_history(window: &Window, path: &str) {
@@ -0,0 +1,309 @@
+use super::css::{CssProperties, CssRule};
+use crate::error::AppError;
+
+pub struct CssSanitizer;
+
+impl CssSanitizer {
+    const ALLOWED_AT_RULES: [&'static str; 11] = [
+        "media",
+        "supports",
+        "keyframes",
+        "-webkit-keyframes",
+        "font-face",
+        "layer",
+        "container",
+        "page",
+        "property",
+        "counter-style",
+        "font-feature-values",
+    ];
+    // statements end in `;` and carry no block; @import and @charset stay out
+    const ALLOWED_STATEMENTS: [&'static str; 2] = ["layer", "namespace"];
+
+    pub fn rule(rule: &CssRule, rejected: &mut Vec<AppError>) -> Option<CssRule> {
+        let result = match rule {
+            CssRule::Scoped { scope, rules } => Ok(CssRule::Scoped {
+                scope: scope.clone(),
+                rules: rules
+                    .iter()
+                    .filter_map(|r| Self::rule(r, rejected))
+                    .collect(),
+            }),
+            CssRule::Block { at, rules } => {
+                Self::at_prelude(at, &Self::ALLOWED_AT_RULES).map(|at| CssRule::Block {
+                    at,
+                    rules: rules
+                        .iter()
+                        .filter_map(|r| Self::rule(r, rejected))
+                        .collect(),
+                })
+            }
+            CssRule::AtProperties { at, properties } => Self::at_properties(at, properties),
+            CssRule::Statement { at } => {
+                Self::at_prelude(at.trim().trim_end_matches(';'), &Self::ALLOWED_STATEMENTS)
+                    .map(|at| CssRule::Statement { at })
+            }
+            // the legacy selector map spells `@font-face { ... }` as a selector
+            CssRule::Style {
+                selector,
+                properties,
+            } if selector.trim_start().starts_with('@') => {
+                Self::at_properties(selector, properties)
+            }
+            CssRule::Style {
+                selector,
+                properties,
+            } => Self::selector(selector).and_then(|selector| {
+                Ok(CssRule::Style {
+                    selector,
+                    properties: Self::properties(properties)?,
+                })
+            }),
+        };
+
+        match result {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                rejected.push(e);
+                None
+            }
+        }
+    }
+
+    fn at_properties(at: &str, properties: &CssProperties) -> Result<CssRule, AppError> {
+        Ok(CssRule::AtProperties {
+            at: Self::at_prelude(at, &Self::ALLOWED_AT_RULES)?,
+            properties: Self::properties(properties)?,
+        })
+    }
+
+    fn selector(selector: &str) -> Result<String, AppError> {
+        let selector = selector.trim();
+        if selector.is_empty() {
+            return Err(AppError::InvalidCss(format!(
+                "invalid selector: {:?}",
+                selector
+            )));
+        }
+        Self::scan(selector, "selector")
+    }
+
+    fn at_prelude(at: &str, allowed: &[&str]) -> Result<String, AppError> {
+        let at = at.trim();
+        let name = at
+            .strip_prefix('@')
+            .and_then(|rest| rest.split(|c: char| c.is_whitespace() || c == '(').next())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if !allowed.contains(&name.as_str()) {
+            return Err(AppError::InvalidCss(format!(
+                "at-rule not allowed: {:?}",
+                at
+            )));
+        }
+        Self::scan(at, "at-rule")
+    }
+
+    fn properties(properties: &CssProperties) -> Result<CssProperties, AppError> {
+        properties
+            .iter()
+            .map(|(prop, value)| Ok((Self::property(prop)?, Self::value(value)?)))
+            .collect::<Result<Vec<_>, AppError>>()
+            .map(CssProperties::List)
+    }
+
+    fn property(prop: &str) -> Result<String, AppError> {
+        let prop = prop.trim();
+        let body = prop.trim_start_matches('-');
+        let dashes = prop.len() - body.len();
+        let valid = dashes <= 2
+            && body.starts_with(|c: char| c.is_ascii_alphabetic() || (dashes == 2 && c == '_'))
+            && body
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if valid {
+            Ok(prop.to_string())
+        } else {
+            Err(AppError::InvalidCss(format!(
+                "invalid property name: {:?}",
+                prop
+            )))
+        }
+    }
+
+    fn value(value: &str) -> Result<String, AppError> {
+        let lowered = value.to_lowercase();
+        if lowered.contains("expression(") || lowered.contains("javascript:") {
+            return Err(AppError::InvalidCss(format!("unsafe value: {:?}", value)));
+        }
+        Self::scan(value.trim(), "value")
+    }
+
+    // rejects block and statement delimiters and comments outside strings, escapes markup
+    // inside them; an open comment would swallow the rest of the rule. a `;` inside parens
+    // belongs to the value, as in unquoted data urls
+    fn scan(input: &str, what: &str) -> Result<String, AppError> {
+        let reject = || AppError::InvalidCss(format!("invalid {}: {:?}", what, input));
+        let mut output = String::with_capacity(input.len());
+        let mut quote: Option<char> = None;
+        let mut open: Vec<char> = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match (quote, ch) {
+                (_, '\\') => {
+                    output.push(ch);
+                    output.push(chars.next().ok_or_else(reject)?);
+                }
+                (Some(q), ch) if ch == q => {
+                    quote = None;
+                    output.push(ch);
+                }
+                (Some(_), '<') => output.push_str("\\3c "),
+                (Some(_), '>') => output.push_str("\\3e "),
+                (Some(_), '\n') => return Err(reject()),
+                (Some(_), ch) => output.push(ch),
+                (None, '"' | '\'') => {
+                    quote = Some(ch);
+                    output.push(ch);
+                }
+                (None, '(' | '[') => {
+                    open.push(ch);
+                    output.push(ch);
+                }
+                (None, ')' | ']') => {
+                    let opener = if ch == ')' { '(' } else { '[' };
+                    if open.pop() != Some(opener) {
+                        return Err(reject());
+                    }
+                    output.push(ch);
+                }
+                (None, ';') if open.contains(&'(') => output.push(ch),
+                (None, '{' | '}' | ';' | '<') => return Err(reject()),
+                (None, '/') if chars.peek() == Some(&'*') => return Err(reject()),
+                (None, '*') if chars.peek() == Some(&'/') => return Err(reject()),
+                (None, ch) => output.push(ch),
+            }
+        }
+
+        if quote.is_some() || !open.is_empty() {
+            return Err(reject());
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(selector: &str, properties: &[(&str, &str)]) -> CssRule {
+        CssRule::Style {
+            selector: selector.to_string(),
+            properties: CssProperties::List(
+                properties
+                    .iter()
+                    .map(|(p, v)| (p.to_string(), v.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn sanitize(rule: &CssRule) -> Result<CssRule, Vec<AppError>> {
+        let mut rejected = Vec::new();
+        CssSanitizer::rule(rule, &mut rejected).ok_or(rejected)
+    }
+
+    #[test]
+    fn keeps_plain_rules() {
+        let rule = style(".card > a:hover", &[("color", "red"), ("--gap", "4px")]);
+        assert_eq!(sanitize(&rule).unwrap(), rule);
+    }
+
+    #[test]
+    fn rejects_block_delimiters_in_values() {
+        assert!(sanitize(&style("a", &[("color", "red; } body { display: none")])).is_err());
+        assert!(sanitize(&style("a { color: red } b", &[("color", "red")])).is_err());
+    }
+
+    #[test]
+    fn rejects_comments_outside_strings() {
+        assert!(sanitize(&style("a", &[("color", "red /* swallow")])).is_err());
+        assert!(sanitize(&style("a", &[("color", "red */")])).is_err());
+        assert!(sanitize(&style("a", &[("content", "\"/* ok */\"")])).is_ok());
+    }
+
+    #[test]
+    fn keeps_semicolons_inside_data_urls() {
+        for value in [
+            "url(data:image/svg+xml;utf8,%3Csvg%3E%3C/svg%3E)",
+            "url(data:image/png;base64,iVBORw0KGgo=) no-repeat",
+        ] {
+            let rule = style("a", &[("background", value)]);
+            assert_eq!(sanitize(&rule).unwrap(), rule);
+        }
+        assert!(sanitize(&style("a", &[("background", "url(x); color: red")])).is_err());
+    }
+
+    #[test]
+    fn rejects_mismatched_brackets() {
+        assert!(sanitize(&style("a", &[("width", "calc(1px]")])).is_err());
+        assert!(sanitize(&style("a[href)", &[("color", "red")])).is_err());
+        assert!(sanitize(&style("a", &[("width", "calc([1px)]")])).is_err());
+        assert!(sanitize(&style("a[href]", &[("width", "calc((1px))")])).is_ok());
+    }
+
+    #[test]
+    fn escapes_markup_inside_strings() {
+        let rule = sanitize(&style("a", &[("content", "\"</style>\"")])).unwrap();
+        assert_eq!(rule, style("a", &[("content", "\"\\3c /style\\3e \"")]));
+    }
+
+    #[test]
+    fn rejects_unsafe_values_and_properties() {
+        assert!(sanitize(&style("a", &[("width", "expression(alert(1))")])).is_err());
+        assert!(sanitize(&style("a", &[("background", "url(javascript:x)")])).is_err());
+        assert!(sanitize(&style("a", &[("co lor", "red")])).is_err());
+    }
+
+    #[test]
+    fn allows_listed_statements_only() {
+        let layer = CssRule::Statement {
+            at: "@layer base, components;".to_string(),
+        };
+        assert_eq!(
+            sanitize(&layer).unwrap(),
+            CssRule::Statement {
+                at: "@layer base, components".to_string()
+            }
+        );
+        let import = CssRule::Statement {
+            at: "@import url(evil.css)".to_string(),
+        };
+        assert!(sanitize(&import).is_err());
+    }
+
+    #[test]
+    fn reads_legacy_at_rule_selectors_as_at_properties() {
+        let rule = sanitize(&style("@font-face", &[("font-family", "x")])).unwrap();
+        assert!(matches!(rule, CssRule::AtProperties { .. }));
+        assert!(sanitize(&style("@import", &[("x", "y")])).is_err());
+    }
+
+    #[test]
+    fn filters_blocks_by_at_rule_name() {
+        let media = CssRule::Block {
+            at: "@media (min-width: 600px)".to_string(),
+            rules: vec![style("a", &[("color", "red")])],
+        };
+        assert_eq!(sanitize(&media).unwrap(), media);
+
+        let unknown = CssRule::Block {
+            at: "@document url(x)".to_string(),
+            rules: vec![],
+        };
+        assert!(sanitize(&unknown).is_err());
+    }
+}
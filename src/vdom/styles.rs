@@ -0,0 +1,90 @@
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CssStyleSheet, Document, HtmlStyleElement};
+
+use crate::utils::log;
+
+// mirror of the rules currently inserted into the dynamic sheet, in sheet order
+#[derive(Debug, Default)]
+pub struct StyleSheetState {
+    rules: Vec<(String, String)>,
+}
+
+impl StyleSheetState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(
+        &mut self,
+        document: &Document,
+        style_tag_id: &str,
+        target: &[(String, String)],
+    ) -> Result<(), JsValue> {
+        let sheet = self.sheet(document, style_tag_id)?;
+
+        for index in (0..self.rules.len()).rev() {
+            if !target.contains(&self.rules[index]) {
+                sheet.delete_rule(index as u32)?;
+                self.rules.remove(index);
+            }
+        }
+
+        let mut index = 0;
+        for rule in target {
+            if self.rules.get(index) == Some(rule) {
+                index += 1;
+                continue;
+            }
+
+            if let Some(stale) = self.rules[index..].iter().position(|r| r == rule) {
+                sheet.delete_rule((index + stale) as u32)?;
+                self.rules.remove(index + stale);
+            }
+
+            match sheet.insert_rule_with_index(&rule.1, index as u32) {
+                Ok(_) => {
+                    self.rules.insert(index, rule.clone());
+                    index += 1;
+                }
+                Err(e) => log(&format!("Rejected CSS rule {}: {:?}", rule.0, e)),
+            }
+        }
+
+        while self.rules.len() > index {
+            sheet.delete_rule(index as u32)?;
+            self.rules.remove(index);
+        }
+
+        Ok(())
+    }
+
+    fn sheet(&mut self, document: &Document, style_tag_id: &str) -> Result<CssStyleSheet, JsValue> {
+        let style_tag = match document.get_element_by_id(style_tag_id) {
+            Some(existing) => existing.dyn_into::<HtmlStyleElement>()?,
+            None => {
+                let head = document.head().ok_or("No <head> element found")?;
+                let style_tag = document
+                    .create_element("style")?
+                    .dyn_into::<HtmlStyleElement>()?;
+                style_tag.set_id(style_tag_id);
+                head.append_child(&style_tag)?;
+                style_tag
+            }
+        };
+
+        let sheet = style_tag
+            .sheet()
+            .ok_or("Dynamic style tag has no sheet")?
+            .dyn_into::<CssStyleSheet>()?;
+
+        // anything that touched the tag behind our back invalidates the mirror
+        if sheet.css_rules()?.length() as usize != self.rules.len() {
+            while sheet.css_rules()?.length() > 0 {
+                sheet.delete_rule(0)?;
+            }
+            self.rules.clear();
+        }
+
+        Ok(sheet)
+    }
+}